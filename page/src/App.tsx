@@ -51,8 +51,71 @@ type MetricEntry = {
 
   avg_write_latency: number;
   avg_read_latency: number;
+
+  phase?: "warmup" | "run";
+  step?: number;
+  workload?: string;
+};
+
+type XAnnotations = NonNullable<NonNullable<ApexChartProps["options"]["annotations"]>["xaxis"]>;
+
+const annotationLabelStyle = {
+  color: "white",
+  background: "transparent",
 };
 
+// Marks where each workload of the schedule starts, and shades its warmup
+function phaseAnnotations(series: HistoryEntry[][]): XAnnotations {
+  if (!series.length) {
+    return [];
+  }
+
+  const metrics = series[0].slice(2);
+  if (!metrics.length) {
+    return [];
+  }
+
+  const start = metrics[0].time_micro;
+  const secs = (x: HistoryEntry) => (x.time_micro - start) / 1000 / 1000;
+
+  const steps = [...new Set(metrics.map((x) => x.step ?? 0))];
+
+  return steps.flatMap((step) => {
+    const entries = metrics.filter((x) => (x.step ?? 0) === step);
+    const warmup = entries.filter((x) => x.phase === "warmup");
+    const x = secs(entries[0]);
+
+    const annotations: XAnnotations = [];
+
+    if (steps.length > 1) {
+      annotations.push({
+        x,
+        borderColor: "#fafafa",
+        label: {
+          text: entries[0].workload ?? `step ${step}`,
+          orientation: "horizontal",
+          style: annotationLabelStyle,
+        },
+      });
+    }
+
+    if (warmup.length) {
+      annotations.push({
+        x,
+        x2: secs(warmup.at(-1)!),
+        fillColor: "#777777",
+        opacity: 0.2,
+        label: {
+          text: "warmup",
+          style: annotationLabelStyle,
+        },
+      });
+    }
+
+    return annotations;
+  });
+}
+
 const chartOptions: ApexChartProps["options"]["chart"] = {
   background: "#030712",
   animations: {
@@ -111,9 +174,12 @@ const baseOptions: ApexChartProps["options"] = {
   }
 }
 
-function LineChart(props: { xaxis?: ApexChartProps["options"]["xaxis"]; yaxis?: ApexChartProps["options"]["yaxis"], title: string, yFormatter: (val: number) => string, series: { name: string, data: { x: number, y: number }[] }[] }) {
+function LineChart(props: { xaxis?: ApexChartProps["options"]["xaxis"]; yaxis?: ApexChartProps["options"]["yaxis"], title: string, yFormatter: (val: number) => string, series: { name: string, data: { x: number, y: number }[] }[], annotations?: XAnnotations }) {
   const options = () => ({
     ...baseOptions,
+    annotations: {
+      xaxis: props.annotations ?? [],
+    },
     title: {
       text: props.title,
       style: {
@@ -188,6 +254,7 @@ function WriteLatencyHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${n.toFixed(1)}µs`}
     title="Average write latency (lower is better)"
     series={series()}
@@ -212,6 +279,7 @@ function ReadLatencyHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${n.toFixed(1)}µs`}
     title="Average read latency (lower is better)"
     series={series()}
@@ -238,6 +306,7 @@ function WriteAmpHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${(n).toFixed(1)}x`}
     title="Write amplification (lower is better)"
     series={series()}
@@ -266,6 +335,7 @@ function DatasetSizeHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${formatThousands(n)} MiB`}
     title="True data set size (higher is better)"
     series={series()}
@@ -290,6 +360,7 @@ function DiskWritesCumulative(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${formatThousands(n)} MiB`}
     title="Written bytes cumulative"
     series={series()}
@@ -314,6 +385,7 @@ function WriteHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${n} ops`}
     title="Write ops cumulative (higher is better)"
     series={series()}
@@ -338,6 +410,7 @@ function ReadHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${n} ops`}
     title="Read ops cumulative (higher is better)"
     series={series()}
@@ -362,6 +435,7 @@ function SpaceAmpHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${(n).toFixed(1)}x`}
     title="Space amplification (lower is better)"
     series={series()}
@@ -390,6 +464,7 @@ function DiskSpaceUsageHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${formatThousands(n)} MiB`}
     title="Disk space usage"
     series={series()}
@@ -414,6 +489,7 @@ function MemoryUsageHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${formatThousands(n)} MiB`}
     title="Memory pressure (lower is better)"
     series={series()}
@@ -438,6 +514,7 @@ function CpuUsageHistory(props: { series: HistoryEntry[][] }) {
   });
 
   return <LineChart
+    annotations={phaseAnnotations(props.series)}
     yFormatter={(n) => `${n} %`}
     title="CPU usage (lower is better)"
     series={series()}
@@ -517,7 +594,9 @@ function App() {
 
     await readFile(file)
       .then((text) => {
-        const items = parseJsonl<HistoryEntry & OpsObject>(text);
+        // NOTE: Only plot the run phase, load phase records have their own type
+        const items = parseJsonl<HistoryEntry & OpsObject & { type: string }>(text)
+          .filter((x) => ["system", "setup", "metrics"].includes(x.type));
         setItems(x => [...x, items]);
 
         setOps(x => {
@@ -1,5 +1,6 @@
 use clap::{Parser, ValueEnum};
 use serde::Serialize;
+use std::time::Duration;
 
 #[derive(Copy, Eq, PartialEq, Debug, Clone, ValueEnum, Serialize)]
 #[clap(rename_all = "kebab_case")]
@@ -17,6 +18,9 @@ pub enum Backend {
 
     #[cfg(feature = "rocksdb")]
     RocksDb,
+
+    #[cfg(feature = "surrealkv")]
+    SurrealKv,
 }
 
 impl std::fmt::Display for Backend {
@@ -38,6 +42,9 @@ impl std::fmt::Display for Backend {
 
                 #[cfg(feature = "rocksdb")]
                 Self::RocksDb => "rocksdb 0.22.0",
+
+                #[cfg(feature = "surrealkv")]
+                Self::SurrealKv => "surrealkv 0.3.0",
             }
         )
     }
@@ -59,6 +66,9 @@ pub enum Workload {
     /// Workload C: Read only
     ///
     /// Application example: user profile cache, where profiles are constructed elsewhere (e.g., Hadoop)
+    ///
+    /// NOTE: Reads go to all preloaded records (`--threads` × `--items`, see `records` in
+    /// the setup record), older versions only read `--items` records
     TaskC,
 
     /// Workload D: Read latest workload with light inserts
@@ -76,6 +86,114 @@ pub enum Workload {
 
     /// Workload G: Read zipfian workload with heavy inserts
     TaskG,
+
+    /// Workload H: Read-modify-write workload (YCSB's workload F)
+    ///
+    /// Application example: user database, where records are read and modified by the user
+    ///
+    /// NOTE: RocksDB writes a blind merge operand instead, leaving the read to later
+    /// reads and compactions, so its numbers are not directly comparable
+    /// (see `rmw_isolation` in the setup record)
+    TaskH,
+
+    /// Workload I: Transfer workload, moving balance between two records per transaction
+    ///
+    /// Application example: bank accounts, where the total balance must never change
+    TaskI,
+
+    /// Workload J: Time series workload, heavy appends with scans of the latest points
+    /// and retention deletes of old ones (see --retention-secs)
+    ///
+    /// Application example: metrics store, one series per thread
+    TaskJ,
+
+    /// Workload K: Queue workload, pushing to the tail and popping from the head
+    ///
+    /// Use --writer-threads and --reader-threads to choose the number of producers and consumers
+    ///
+    /// Application example: durable work queue
+    TaskK,
+
+    /// Workload L: Blob workload, overwriting records with large values (see --blob-size-min)
+    ///
+    /// Application example: image or document store
+    TaskL,
+
+    /// Workload M: Secondary index maintenance, 50% updates of a record together with its
+    /// index entries, 50% lookups by secondary key (see --secondary-indexes)
+    ///
+    /// Application example: user table with lookups by email or country
+    TaskM,
+}
+
+impl Workload {
+    /// Key distribution the workload was designed around
+    pub fn default_key_distribution(&self) -> KeyDistribution {
+        match self {
+            Self::TaskD | Self::TaskE => KeyDistribution::Newest,
+            _ => KeyDistribution::Zipfian,
+        }
+    }
+}
+
+/// How reads and updates choose among the existing keys
+#[derive(Copy, Debug, Clone, ValueEnum, Serialize, PartialEq, Eq)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "snake_case")]
+pub enum KeyDistribution {
+    /// Every key is equally likely
+    Uniform,
+
+    /// The first keys are the most popular (see --zipf-theta)
+    Zipfian,
+
+    /// Zipfian popularity, but the popular keys are spread over the keyspace
+    ScrambledZipfian,
+
+    /// The most recently inserted keys are the most popular (zipfian)
+    Latest,
+
+    /// Always the most recently inserted key
+    Newest,
+
+    /// A small set of keys gets most operations (see --hotspot-*)
+    Hotspot,
+
+    /// Goes through the keys in order, wrapping around
+    Sequential,
+}
+
+/// Layout of the record id at the start of each key
+#[derive(Copy, Debug, Clone, ValueEnum, Serialize, PartialEq, Eq)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "snake_case")]
+pub enum KeyOrder {
+    /// Big-endian record id, keys sort in insertion order
+    Ordered,
+
+    /// Hashed record id, keys are spread over the keyspace
+    Random,
+}
+
+/// How value sizes are picked
+#[derive(Copy, Debug, Clone, ValueEnum, Serialize, PartialEq, Eq)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ValueSizeDistribution {
+    /// Always --value-size
+    Fixed,
+
+    /// Between --value-size-min and --value-size-max
+    Uniform,
+
+    /// Around --value-size (see --value-size-std-dev)
+    Normal,
+
+    /// Around --value-size, with a long tail of large values (see --value-size-std-dev)
+    LogNormal,
+
+    /// Sizes and weights read from --value-size-histogram
+    Histogram,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, clap::ValueEnum)]
@@ -105,21 +223,95 @@ pub struct Args {
     #[arg(long, value_enum)]
     pub backend: Backend,
 
-    #[arg(long, value_enum)]
-    pub workload: Workload,
+    /// Keys looked up per read, more than 1 uses batched lookups (multi get)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub read_batch_size: u32,
+
+    /// Workloads to run one after another, against the same loaded data
+    #[arg(long, value_enum, value_delimiter = ',', required = true)]
+    pub workload: Vec<Workload>,
 
     #[arg(long, default_value_t = 1)]
     pub threads: u8,
 
+    /// Threads only reading from the shared keyspace, replaces --threads in the run phase
     #[arg(long)]
-    pub items: u32,
+    pub reader_threads: Option<u8>,
 
+    /// Threads only overwriting records of the shared keyspace, replaces --threads in the run phase
     #[arg(long)]
+    pub writer_threads: Option<u8>,
+
+    #[arg(long)]
+    pub items: u32,
+
+    /// Threads inserting the initial records (load phase)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    pub load_threads: u8,
+
+    /// Records written per batch in the load phase
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub load_batch_size: u32,
+
+    /// Key size, keys start with an 8 byte record id
+    #[arg(long, value_parser = clap::value_parser!(u8).range(8..))]
     pub key_size: u8,
 
+    /// Pick key sizes between --key-size and this, per record
+    #[arg(long, value_parser = clap::value_parser!(u8).range(8..))]
+    pub key_size_max: Option<u8>,
+
+    /// Whether keys are inserted in order, or spread randomly over the keyspace
+    #[arg(long, value_enum, default_value_t = KeyOrder::Ordered)]
+    pub key_order: KeyOrder,
+
     #[arg(long)]
     pub value_size: u32,
 
+    /// How value sizes are picked
+    #[arg(long, value_enum, default_value_t = ValueSizeDistribution::Fixed)]
+    pub value_size_distribution: ValueSizeDistribution,
+
+    /// Smallest value size [default: 0]
+    #[arg(long)]
+    pub value_size_min: Option<u32>,
+
+    /// Largest value size [default: 4 * --value-size]
+    #[arg(long)]
+    pub value_size_max: Option<u32>,
+
+    /// Standard deviation of value sizes [default: --value-size / 4]
+    #[arg(long)]
+    pub value_size_std_dev: Option<u32>,
+
+    /// File with one `<size> <weight>` pair per line
+    #[arg(long)]
+    pub value_size_histogram: Option<String>,
+
+    /// Target compression ratio of values, 1.0 being incompressible
+    #[arg(long, default_value_t = 1.0)]
+    pub compressibility: f64,
+
+    /// Number of partitions (tables, buckets, column families...) to spread keys over
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub partitions: u16,
+
+    /// Key distribution for reads and updates [default: depends on workload]
+    #[arg(long, value_enum)]
+    pub key_distribution: Option<KeyDistribution>,
+
+    /// Skew of the zipfian key distributions
+    #[arg(long, default_value_t = 0.99)]
+    pub zipf_theta: f64,
+
+    /// Fraction of keys that are hot in the hotspot key distribution
+    #[arg(long, default_value_t = 0.2)]
+    pub hotspot_fraction: f64,
+
+    /// Fraction of operations that go to hot keys in the hotspot key distribution
+    #[arg(long, default_value_t = 0.8)]
+    pub hotspot_ops_fraction: f64,
+
     /// Block size for LSM-trees
     #[arg(long, default_value_t = 4_096)]
     pub lsm_block_size: u16,
@@ -136,6 +328,46 @@ pub struct Args {
     #[arg(long, default_value_t = 16_000_000)]
     pub cache_size: u32,
 
+    /// Run redb on an instrumented storage backend that counts I/O calls
+    #[arg(long, default_value_t = false)]
+    pub redb_instrument_io: bool,
+
+    /// Latency injected into every redb read and write (implies --redb-instrument-io)
+    #[arg(long, default_value_t = 0)]
+    pub redb_io_latency_us: u32,
+
+    /// Latency injected into every redb sync (implies --redb-instrument-io)
+    #[arg(long, default_value_t = 0)]
+    pub redb_sync_latency_us: u32,
+
+    /// Fail every redb write after the given number of writes (implies --redb-instrument-io)
+    #[arg(long)]
+    pub redb_fail_after_writes: Option<u64>,
+
+    /// Store large rocksdb values in blob files (key-value separation)
+    #[arg(long, default_value_t = false)]
+    pub rocksdb_blob_files: bool,
+
+    /// Smallest value stored in a rocksdb blob file
+    #[arg(long, default_value_t = 4_096)]
+    pub rocksdb_min_blob_size: u32,
+
+    /// Smallest value written by the blob workload
+    #[arg(long, default_value_t = 64 * 1_024)]
+    pub blob_size_min: u32,
+
+    /// Largest value written by the blob workload
+    #[arg(long, default_value_t = 4 * 1_024 * 1_024)]
+    pub blob_size_max: u32,
+
+    /// Secondary indexes maintained by the index workload
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    pub secondary_indexes: u8,
+
+    /// Distinct secondary keys per index in the index workload
+    #[arg(long, default_value_t = 1_000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub index_cardinality: u32,
+
     #[arg(long, default_value = "log.jsonl")]
     pub out: String,
 
@@ -147,4 +379,82 @@ pub struct Args {
 
     #[arg(long, default_value_t = 1)]
     pub minutes: u16,
+
+    /// Minutes to run each workload for, in the order of --workload
+    ///
+    /// Workloads without an entry run for --seconds or --minutes
+    #[arg(long, value_delimiter = ',')]
+    pub phase_minutes: Vec<u16>,
+
+    /// Seconds to run each workload for, instead of --minutes
+    #[arg(long)]
+    pub seconds: Option<u32>,
+
+    /// Operations to run per workload, split evenly over the worker threads
+    ///
+    /// A workload ends once they are done, regardless of --minutes
+    #[arg(long)]
+    pub ops: Option<u64>,
+
+    /// Count --ops per worker thread instead of in total
+    #[arg(long, default_value_t = false)]
+    pub ops_per_thread: bool,
+
+    /// Time series points older than this get range deleted
+    #[arg(long, default_value_t = 60)]
+    pub retention_secs: u32,
+
+    /// Time range of the latest points scanned by time series reads
+    #[arg(long, default_value_t = 1_000)]
+    pub scan_window_ms: u32,
+
+    /// Seconds to run each workload before measuring, to warm up caches
+    #[arg(long, default_value_t = 0)]
+    pub warmup_secs: u32,
+
+    /// Adds ops and average latencies of each thread to the metrics records
+    ///
+    /// The last entry counts all threads that are not workers, e.g. time series retention
+    #[arg(long, default_value_t = false)]
+    pub thread_metrics: bool,
+}
+
+impl Args {
+    /// Whether the run phase uses separate reader and writer threads
+    pub fn uses_thread_pools(&self) -> bool {
+        self.reader_threads.is_some() || self.writer_threads.is_some()
+    }
+
+    /// Number of threads the run phase spawns
+    pub fn worker_threads(&self) -> usize {
+        if self.uses_thread_pools() {
+            usize::from(self.reader_threads.unwrap_or(0))
+                + usize::from(self.writer_threads.unwrap_or(0))
+        } else {
+            usize::from(self.threads)
+        }
+    }
+
+    /// How long the `step`-th workload of the schedule runs for
+    pub fn phase_duration(&self, step: usize) -> Duration {
+        match (self.phase_minutes.get(step), self.seconds) {
+            (Some(minutes), _) => Duration::from_secs(u64::from(*minutes) * 60),
+            (None, Some(seconds)) => Duration::from_secs(seconds.into()),
+            (None, None) => Duration::from_secs(u64::from(self.minutes) * 60),
+        }
+    }
+
+    /// Operations worker thread `idx` runs per workload, if limited by --ops
+    pub fn thread_op_limit(&self, idx: usize) -> Option<u64> {
+        let ops = self.ops?;
+
+        if self.ops_per_thread {
+            return Some(ops);
+        }
+
+        // NOTE: Spread the remainder, so the shares add up to exactly --ops
+        let threads = self.worker_threads() as u64;
+        let idx = idx as u64;
+        Some(ops * (idx + 1) / threads - ops * idx / threads)
+    }
 }
@@ -0,0 +1,106 @@
+use crate::latency::Latency;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+/// Op counters and latencies of one thread
+///
+/// Aligned, so threads counting into neighbouring slots never share a cache line
+#[derive(Default)]
+#[repr(align(128))]
+pub struct Counters {
+    pub write_ops: AtomicU64,
+    pub read_ops: AtomicU64,
+    pub delete_ops: AtomicU64,
+    pub scan_ops: AtomicU64,
+
+    pub write_latency: Latency,
+    pub read_latency: Latency,
+
+    /// Deletes are range deletes, no matter how many records they removed
+    pub delete_latency: Latency,
+    pub scan_latency: Latency,
+
+    /// Logical bytes (keys + values) written
+    pub write_bytes: AtomicU64,
+
+    /// Value bytes written and read, taken by the metrics thread to compute bandwidth
+    pub value_write_bytes: AtomicU64,
+    pub value_read_bytes: AtomicU64,
+
+    pub rmw_ops: AtomicU64,
+    pub rmw_latency: Latency,
+
+    /// Read-modify-writes that had to be retried because of a conflict
+    pub rmw_conflicts: AtomicU64,
+
+    /// Batches of `multi_get`, their keys also count as `read_ops`
+    pub multi_get_ops: AtomicU64,
+    pub multi_get_latency: Latency,
+
+    pub pop_ops: AtomicU64,
+    pub pop_latency: Latency,
+
+    /// Pops that found the queue empty, they count neither as pops nor towards latencies
+    pub empty_pops: AtomicU64,
+
+    /// Pops that were retried because another consumer got the item first
+    pub pop_conflicts: AtomicU64,
+
+    pub tx_ops: AtomicU64,
+    pub tx_latency: Latency,
+
+    /// Transactions that were aborted because of a conflict, and retried
+    pub tx_aborts: AtomicU64,
+
+    /// Writes that failed to commit (see `--redb-fail-after-writes`),
+    /// they count neither as ops nor towards latencies
+    pub failed_writes: AtomicU64,
+}
+
+impl Counters {
+    /// Every kind of operation, with its op counter and latencies
+    pub fn ops(&self) -> [(&'static str, &AtomicU64, &Latency); 8] {
+        [
+            ("write", &self.write_ops, &self.write_latency),
+            ("read", &self.read_ops, &self.read_latency),
+            ("rmw", &self.rmw_ops, &self.rmw_latency),
+            ("tx", &self.tx_ops, &self.tx_latency),
+            ("multi_get", &self.multi_get_ops, &self.multi_get_latency),
+            ("delete", &self.delete_ops, &self.delete_latency),
+            ("scan", &self.scan_ops, &self.scan_latency),
+            ("pop", &self.pop_ops, &self.pop_latency),
+        ]
+    }
+
+    /// Zeroes the op and latency counters
+    ///
+    /// `write_bytes` is kept, it tracks the dataset size
+    pub fn reset(&self) {
+        for (_, ops, latency) in self.ops() {
+            ops.store(0, Relaxed);
+            latency.reset();
+        }
+
+        for counter in [
+            &self.rmw_conflicts,
+            &self.empty_pops,
+            &self.pop_conflicts,
+            &self.tx_aborts,
+            &self.failed_writes,
+        ] {
+            counter.store(0, Relaxed);
+        }
+    }
+}
+
+/// A value on cache lines of its own, for per-thread values kept next to each other
+#[derive(Default)]
+#[repr(align(128))]
+pub struct Padded<T>(pub T);
+
+impl<T> std::ops::Deref for Padded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
@@ -1,21 +1,57 @@
+use crate::counters::Counters;
+use crate::latency::Latency;
 use crate::Args;
 use nebari::{io::fs::StdFile, tree::Unversioned};
-use redb::TableDefinition;
+use redb::{ReadableTable, TableDefinition};
 use std::{
-    sync::{atomic::AtomicU64, Arc},
+    sync::{atomic::AtomicU64, Arc, Mutex, MutexGuard, RwLock},
     time::Instant,
 };
 
 #[derive(Clone)]
 pub struct DatabaseWrapper {
     pub inner: GenericDatabase,
-    pub write_ops: Arc<AtomicU64>,
-    pub read_ops: Arc<AtomicU64>,
-    pub delete_ops: Arc<AtomicU64>,
-    pub scan_ops: Arc<AtomicU64>,
 
-    pub write_latency: Arc<AtomicU64>,
-    pub read_latency: Arc<AtomicU64>,
+    /// Counters of each worker thread, the last slot is shared by all other threads
+    pub counters: Arc<Vec<Counters>>,
+
+    /// Slot of `counters` this handle counts into
+    pub slot: usize,
+
+    /// Serializes read-modify-writes on backends without transactions
+    pub key_locks: Arc<KeyLocks>,
+}
+
+/// Striped locks, keys are spread over them by hash
+pub struct KeyLocks(Vec<Mutex<()>>);
+
+impl Default for KeyLocks {
+    fn default() -> Self {
+        Self((0..64).map(|_| Mutex::default()).collect())
+    }
+}
+
+impl KeyLocks {
+    pub fn lock(&self, key: &[u8]) -> MutexGuard<'_, ()> {
+        self.0[partition_idx(key, self.0.len())].lock().unwrap()
+    }
+
+    /// Locks two keys, always in the same order to not deadlock
+    pub fn lock_both(&self, a: &[u8], b: &[u8]) -> Vec<MutexGuard<'_, ()>> {
+        let mut stripes = [
+            partition_idx(a, self.0.len()),
+            partition_idx(b, self.0.len()),
+        ];
+        stripes.sort_unstable();
+
+        let mut guards = vec![self.0[stripes[0]].lock().unwrap()];
+
+        if stripes[1] != stripes[0] {
+            guards.push(self.0[stripes[1]].lock().unwrap());
+        }
+
+        guards
+    }
 }
 
 impl std::ops::Deref for DatabaseWrapper {
@@ -26,55 +62,290 @@ impl std::ops::Deref for DatabaseWrapper {
     }
 }
 
+/// Each backend holds one partition (table, bucket, column family...)
+/// per `--partitions`, keys are spread over them by hash
 #[derive(Clone)]
 pub enum GenericDatabase {
     Fjall {
         keyspace: fjall::Keyspace,
-        db: fjall::PartitionHandle,
+        partitions: Vec<fjall::PartitionHandle>,
+    },
+    Sled {
+        db: sled::Db,
+        trees: Vec<sled::Tree>,
     },
-    Sled(sled::Db),
     // Bloodstone(bloodstone::Db),
-    Jamm(jammdb::DB),
-    Persy(persy::Persy),
-    Redb(Arc<redb::Database>),
+    Jamm {
+        db: jammdb::DB,
+        buckets: Vec<String>,
+
+        tx_start: Arc<JammTxStart>,
+    },
+    Persy {
+        db: persy::Persy,
+
+        /// Segment and index name of each partition
+        partitions: Vec<(String, String)>,
+    },
+    Redb {
+        db: Arc<redb::Database>,
+        tables: Vec<String>,
+    },
     Nebari {
         roots: nebari::Roots<StdFile>,
-        tree: nebari::Tree<Unversioned, StdFile>,
+        trees: Vec<nebari::Tree<Unversioned, StdFile>>,
     },
 
     #[cfg(feature = "heed")]
     Heed {
-        db: heed::Database<heed::types::Bytes, heed::types::Bytes>,
+        dbs: Vec<heed::Database<heed::types::Bytes, heed::types::Bytes>>,
         env: heed::Env,
     },
 
     #[cfg(feature = "rocksdb")]
-    RocksDb(Arc<rocksdb::DB>),
+    RocksDb {
+        db: Arc<rocksdb::DB>,
+        column_families: Vec<String>,
+    },
+
+    #[cfg(feature = "surrealkv")]
+    SurrealKv(Arc<surrealkv::Store>),
+}
+
+/// Name of the N-th partition, the first one keeps the historic "data" name
+pub fn partition_name(idx: usize) -> String {
+    if idx == 0 {
+        "data".into()
+    } else {
+        format!("data{idx}")
+    }
+}
+
+/// Picks the partition a key belongs to (FNV-1a)
+fn partition_idx(key: &[u8], count: usize) -> usize {
+    if count == 1 {
+        return 0;
+    }
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for byte in key {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    (hash % count as u64) as usize
+}
+
+/// Adds `delta` to the little endian counter in the first 8 bytes of a value
+fn add_to_counter(value: &mut Vec<u8>, delta: u64) {
+    if value.len() < 8 {
+        value.resize(8, 0);
+    }
+
+    let counter = u64::from_le_bytes(value[..8].try_into().unwrap()).wrapping_add(delta);
+    value[..8].copy_from_slice(&counter.to_le_bytes());
+}
+
+/// Counter stored in the first 8 bytes of a value, shorter values are zero padded
+pub fn counter(value: &[u8]) -> u64 {
+    let mut counter = [0; 8];
+    let len = value.len().min(8);
+    counter[..len].copy_from_slice(&value[..len]);
+    u64::from_le_bytes(counter)
+}
+
+/// Value with `delta` added to its counter
+fn adjusted(value: Option<&[u8]>, delta: u64) -> Vec<u8> {
+    let mut value = value.map(<[u8]>::to_vec).unwrap_or_default();
+    add_to_counter(&mut value, delta);
+    value
+}
+
+/// The modification of read-modify-writes: increments the value's counter
+fn incremented(value: Option<&[u8]>) -> Vec<u8> {
+    adjusted(value, 1)
+}
+
+/// Whether a surrealkv commit failed because another transaction touched the same keys
+///
+/// surrealkv reports write conflicts as read conflicts too
+#[cfg(feature = "surrealkv")]
+fn is_surrealkv_conflict(e: &surrealkv::Error) -> bool {
+    matches!(e, surrealkv::Error::TransactionReadConflict)
+}
+
+/// Guards jammdb against recycling pages of a read transaction that is being opened
+///
+/// jammdb reads the meta page before registering a read transaction, so two write
+/// transactions starting in between could free and reuse the pages of its snapshot
+#[derive(Default)]
+pub struct JammTxStart {
+    /// Held for the whole write transaction, like jammdb's own file lock
+    writer: Mutex<()>,
+
+    /// Held exclusively while a write transaction is opened
+    opening: RwLock<()>,
+}
+
+/// Opens a jammdb transaction, write transactions return the guard that has to outlive them
+fn jamm_tx<'a>(
+    db: &'a jammdb::DB,
+    tx_start: &'a JammTxStart,
+    writable: bool,
+) -> (jammdb::Tx<'a>, Option<MutexGuard<'a, ()>>) {
+    if writable {
+        let writer = tx_start.writer.lock().unwrap();
+        let _opening = tx_start.opening.write().unwrap();
+        (db.tx(true).unwrap(), Some(writer))
+    } else {
+        let _opening = tx_start.opening.read().unwrap();
+        (db.tx(false).unwrap(), None)
+    }
+}
+
+/// Times a Persy transaction is retried before giving up
+///
+/// Conflicts are reported by prepare like any other error, so retries need a bound
+/// to not spin forever on errors that are not going away
+const PERSY_MAX_RETRIES: u64 = 1_000;
+
+/// Prepares and commits a Persy transaction, returning `false` if it needs to be retried
+///
+/// Persy transactions fail on prepare if another one touched the same records
+fn persy_commit(tx: persy::Transaction, retries: &mut u64) -> bool {
+    match tx.prepare() {
+        Ok(prepared) => {
+            prepared.commit().unwrap();
+            true
+        }
+        Err(e) => {
+            *retries += 1;
+            assert!(
+                *retries <= PERSY_MAX_RETRIES,
+                "persy transaction failed {retries} times: {e}"
+            );
+            false
+        }
+    }
+}
+
+/// A Nebari transaction over the partitions of some keys
+struct NebariTransaction {
+    tx: nebari::ExecutingTransaction<StdFile>,
+
+    /// Partition of each tree of the transaction, ascending
+    partitions: Vec<usize>,
+    partition_count: usize,
+}
+
+impl NebariTransaction {
+    /// Nebari locks the trees of a transaction until it is done, which isolates it,
+    /// so trees are always locked in the same order to not deadlock
+    fn begin<'a>(
+        roots: &nebari::Roots<StdFile>,
+        partition_count: usize,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Self {
+        use nebari::tree::Root;
+
+        let mut partitions = keys
+            .into_iter()
+            .map(|key| partition_idx(key, partition_count))
+            .collect::<Vec<_>>();
+        partitions.sort_unstable();
+        partitions.dedup();
+
+        let trees = partitions
+            .iter()
+            .map(|&idx| Unversioned::tree(partition_name(idx)))
+            .collect::<Vec<_>>();
+
+        Self {
+            tx: roots.transaction(&trees).unwrap(),
+            partitions,
+            partition_count,
+        }
+    }
+
+    /// Tree of the partition `key` belongs to, locked until the guard is dropped,
+    /// which needs to happen before committing
+    fn tree(&self, key: &[u8]) -> nebari::LockedTransactionTree<'_, Unversioned, StdFile> {
+        let idx = partition_idx(key, self.partition_count);
+        let pos = self.partitions.binary_search(&idx).unwrap();
+
+        self.tx.tree(pos).unwrap()
+    }
+
+    fn commit(self) {
+        self.tx.commit().unwrap();
+    }
 }
 
-const TABLE: TableDefinition<&[u8], Vec<u8>> = TableDefinition::new("data");
+/// Merge operator applying read-modify-writes, each operand being a counter delta
+///
+/// Adding deltas is associative, so partial merges work the same
+#[cfg(feature = "rocksdb")]
+pub fn rocksdb_counter_merge(
+    _key: &[u8],
+    existing: Option<&[u8]>,
+    operands: &rocksdb::MergeOperands,
+) -> Option<Vec<u8>> {
+    let mut value = existing.map(<[u8]>::to_vec).unwrap_or_default();
+
+    for operand in operands.iter() {
+        add_to_counter(&mut value, counter(operand));
+    }
+
+    Some(value)
+}
+
+pub fn redb_table(name: &str) -> TableDefinition<'_, &'static [u8], Vec<u8>> {
+    TableDefinition::new(name)
+}
 
 impl DatabaseWrapper {
     pub fn insert(&self, key: &[u8], value: &[u8], durable: bool, args: Arc<Args>) {
         match &self.inner {
             #[cfg(feature = "rocksdb")]
-            GenericDatabase::RocksDb(db) => {
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                let cf = &column_families[partition_idx(key, column_families.len())];
+                let cf = db.cf_handle(cf).unwrap();
+
                 let start = Instant::now();
 
-                db.put(key, value).unwrap();
+                db.put_cf(cf, key, value).unwrap();
 
                 if durable {
                     db.flush_wal(true).unwrap();
                 }
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                use surrealkv::Durability::{Eventual, Immediate};
+
+                let start = Instant::now();
+
+                let mut tx = db.begin().unwrap();
+                tx.set_durability(if durable { Immediate } else { Eventual });
+                tx.set(key, value).unwrap();
+
+                // NOTE: surrealkv commits are async, but don't actually need a runtime
+                pollster::block_on(tx.commit()).unwrap();
+
+                self.local().write_latency.record(start.elapsed());
             }
 
             #[cfg(feature = "heed")]
-            GenericDatabase::Heed { env, db } => {
+            GenericDatabase::Heed { env, dbs } => {
+                let db = &dbs[partition_idx(key, dbs.len())];
+
                 let start = Instant::now();
 
                 let mut wtxn = env.write_txn().unwrap();
@@ -82,29 +353,30 @@ impl DatabaseWrapper {
 
                 wtxn.commit().unwrap();
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
             }
-            GenericDatabase::Nebari { roots: _, tree } => {
+            GenericDatabase::Nebari { roots: _, trees } => {
                 if !durable {
                     log::warn!("WARNING: Nebari does not support eventual durability");
                 }
 
+                let tree = &trees[partition_idx(key, trees.len())];
+
                 let key = key.to_vec();
-                let value = key.to_vec();
+                let value = value.to_vec();
 
                 let start = Instant::now();
 
                 tree.set(key, value).unwrap();
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
             }
-            GenericDatabase::Fjall { keyspace, db } => {
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                let db = &partitions[partition_idx(key, partitions.len())];
+
                 let start = Instant::now();
 
                 db.insert(key, value).unwrap();
@@ -113,24 +385,20 @@ impl DatabaseWrapper {
                     keyspace.persist(fjall::FlushMode::SyncAll).unwrap();
                 }
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
             }
-            GenericDatabase::Sled(db) => {
+            GenericDatabase::Sled { db, trees } => {
+                let tree = &trees[partition_idx(key, trees.len())];
+
                 let start = Instant::now();
 
-                db.insert(key, value).unwrap();
+                tree.insert(key, value).unwrap();
 
                 if durable {
                     db.flush().unwrap();
                 }
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
             }
             // GenericDatabase::Bloodstone(db) => {
             //     let start = Instant::now();
@@ -153,125 +421,1739 @@ impl DatabaseWrapper {
             //         std::sync::atomic::Ordering::Relaxed,
             //     );
             // }
-            GenericDatabase::Jamm(db) => {
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
                 if !durable {
                     log::warn!("WARNING: JammDB does not support eventual durability",);
                 }
 
                 let start = Instant::now();
 
-                let tx = db.tx(true).unwrap();
-                let bucket = tx.get_bucket("data").unwrap();
+                let bucket = &buckets[partition_idx(key, buckets.len())];
+
+                let (tx, _writer) = jamm_tx(db, tx_start, true);
+                let bucket = tx.get_bucket(bucket.as_str()).unwrap();
                 bucket.put(key, value).unwrap();
                 tx.commit().unwrap();
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
             }
-            GenericDatabase::Persy(db) => {
-                use persy::{PersyId, TransactionConfig};
+            GenericDatabase::Persy { db, partitions } => {
+                use persy::{ByteVec, PersyId, TransactionConfig};
 
-                let key = String::from_utf8_lossy(key);
-                let key = key.to_string();
+                let (segment, index) = &partitions[partition_idx(key, partitions.len())];
+
+                let key = ByteVec::from(key.to_vec());
 
                 let start = Instant::now();
 
                 let mut tx = db
                     .begin_with(TransactionConfig::new().set_background_sync(!durable))
                     .unwrap();
-                let id = tx.insert("data", value).unwrap();
+                let id = tx.insert(segment, value).unwrap();
 
-                tx.put::<String, PersyId>("primary", key, id).unwrap();
+                tx.put::<ByteVec, PersyId>(index, key, id).unwrap();
                 let prepared = tx.prepare().unwrap();
 
                 prepared.commit().unwrap();
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
             }
-            GenericDatabase::Redb(db) => {
+            GenericDatabase::Redb { db, tables } => {
                 use redb::Durability::{Eventual, Immediate};
 
+                let table = redb_table(&tables[partition_idx(key, tables.len())]);
+
                 let start = Instant::now();
 
-                let mut write_txn = db.begin_write().unwrap();
+                let durability = if durable { Immediate } else { Eventual };
 
-                write_txn.set_durability(if durable { Immediate } else { Eventual });
+                let committed = self.redb_write(db, durability, |write_txn| {
+                    let mut table = write_txn.open_table(table)?;
+                    table.insert(key, value.to_vec())?;
 
-                {
-                    let mut table = write_txn.open_table(TABLE).unwrap();
-                    table.insert(key, value.to_vec()).unwrap();
+                    Ok(())
+                });
+
+                if committed.is_none() {
+                    return;
                 }
-                write_txn.commit().unwrap();
 
-                self.write_latency.fetch_add(
-                    start.elapsed().as_micros() as u64,
-                    std::sync::atomic::Ordering::Relaxed,
-                );
+                self.local().write_latency.record(start.elapsed());
             }
         }
 
-        self.write_ops
+        self.local()
+            .write_ops
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        self.local().write_bytes.fetch_add(
+            (key.len() + value.len()) as u64,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+
+        self.local()
+            .value_write_bytes
+            .fetch_add(value.len() as u64, std::sync::atomic::Ordering::Relaxed);
     }
 
-    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+    /// Writes all items in one batch (or transaction), where the backend supports it
+    pub fn insert_batch(&self, items: &[(Vec<u8>, Vec<u8>)], durable: bool) {
+        let start = Instant::now();
+
+        if !self.apply_batch(items, &[], durable) {
+            return;
+        }
+
+        self.local().write_latency.record(start.elapsed());
+
+        self.local()
+            .write_ops
+            .fetch_add(items.len() as u64, std::sync::atomic::Ordering::Relaxed);
+
+        self.count_written(items);
+    }
+
+    /// Writes and deletes keys together in one batch (or transaction),
+    /// counted as a single write
+    pub fn write_batch(&self, puts: &[(Vec<u8>, Vec<u8>)], deletes: &[Vec<u8>], durable: bool) {
         let start = Instant::now();
 
-        let item = match &self.inner {
+        if !self.apply_batch(puts, deletes, durable) {
+            return;
+        }
+
+        self.local().write_latency.record(start.elapsed());
+
+        self.local()
+            .write_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        self.count_written(puts);
+    }
+
+    fn count_written(&self, items: &[(Vec<u8>, Vec<u8>)]) {
+        self.local().write_bytes.fetch_add(
+            items
+                .iter()
+                .map(|(key, value)| (key.len() + value.len()) as u64)
+                .sum(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+
+        self.local().value_write_bytes.fetch_add(
+            items.iter().map(|(_, value)| value.len() as u64).sum(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    /// Returns `false` if the batch failed to commit
+    fn apply_batch(&self, puts: &[(Vec<u8>, Vec<u8>)], deletes: &[Vec<u8>], durable: bool) -> bool {
+        match &self.inner {
             #[cfg(feature = "rocksdb")]
-            GenericDatabase::RocksDb(db) => db.get(key).unwrap().map(|x| x.to_vec()),
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                let cf = |key: &[u8]| {
+                    db.cf_handle(&column_families[partition_idx(key, column_families.len())])
+                        .unwrap()
+                };
+
+                let mut batch = rocksdb::WriteBatch::default();
+
+                for (key, value) in puts {
+                    batch.put_cf(cf(key), key, value);
+                }
+                for key in deletes {
+                    batch.delete_cf(cf(key), key);
+                }
+
+                db.write(batch).unwrap();
+
+                if durable {
+                    db.flush_wal(true).unwrap();
+                }
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                use surrealkv::Durability::{Eventual, Immediate};
+
+                let mut tx = db.begin().unwrap();
+                tx.set_durability(if durable { Immediate } else { Eventual });
+
+                for (key, value) in puts {
+                    tx.set(key, value).unwrap();
+                }
+                for key in deletes {
+                    tx.delete(key).unwrap();
+                }
+
+                pollster::block_on(tx.commit()).unwrap();
+            }
 
             #[cfg(feature = "heed")]
-            GenericDatabase::Heed { db, env } => {
-                let rtxn = env.read_txn().unwrap();
-                let ret = db.get(&rtxn, key).unwrap();
-                ret.map(|x| x.to_vec())
+            GenericDatabase::Heed { env, dbs } => {
+                let mut wtxn = env.write_txn().unwrap();
+
+                for (key, value) in puts {
+                    let db = &dbs[partition_idx(key, dbs.len())];
+                    db.put(&mut wtxn, key, value).unwrap();
+                }
+                for key in deletes {
+                    let db = &dbs[partition_idx(key, dbs.len())];
+                    db.delete(&mut wtxn, key).unwrap();
+                }
+
+                wtxn.commit().unwrap();
             }
+            GenericDatabase::Nebari { roots, trees } => {
+                let keys = puts.iter().map(|(key, _)| key).chain(deletes);
+                let tx = NebariTransaction::begin(roots, trees.len(), keys.map(Vec::as_slice));
 
-            GenericDatabase::Nebari { roots: _, tree } => {
-                let item = tree.get(key).unwrap();
-                item.map(|x| x.to_vec())
+                for (key, value) in puts {
+                    tx.tree(key).set(key.clone(), value.clone()).unwrap();
+                }
+                for key in deletes {
+                    tx.tree(key).remove(key).unwrap();
+                }
+
+                tx.commit();
             }
-            GenericDatabase::Fjall { keyspace: _, db } => db.get(key).unwrap().map(|x| x.to_vec()),
-            GenericDatabase::Sled(db) => db.get(key).unwrap().map(|x| x.to_vec()),
-            // GenericDatabase::Bloodstone(db) => db.get(key).unwrap().map(|x| x.to_vec()),
-            GenericDatabase::Jamm(db) => {
-                let tx = db.tx(false).unwrap();
-                let bucket = tx.get_bucket("data").unwrap();
-                bucket.get(key).map(|item| item.kv().value().into())
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                let mut batch = keyspace.batch();
+
+                for (key, value) in puts {
+                    let partition = &partitions[partition_idx(key, partitions.len())];
+                    batch.insert(partition, key, value);
+                }
+                for key in deletes {
+                    let partition = &partitions[partition_idx(key, partitions.len())];
+                    batch.remove(partition, key);
+                }
+
+                batch.commit().unwrap();
+
+                if durable {
+                    keyspace.persist(fjall::FlushMode::SyncAll).unwrap();
+                }
             }
-            GenericDatabase::Persy(db) => {
-                let key = String::from_utf8_lossy(key);
+            GenericDatabase::Sled { db, trees } => {
+                use sled::transaction::TransactionResult;
+                use sled::Transactional;
 
-                let mut read_id = db
-                    .get::<String, persy::PersyId>("primary", &key.to_string())
+                // NOTE: Batches are only atomic per tree, so apply them in a transaction
+                let mut batches = vec![sled::Batch::default(); trees.len()];
+
+                for (key, value) in puts {
+                    batches[partition_idx(key, trees.len())]
+                        .insert(key.as_slice(), value.as_slice());
+                }
+                for key in deletes {
+                    batches[partition_idx(key, trees.len())].remove(key.as_slice());
+                }
+
+                let result: TransactionResult<()> = trees.as_slice().transaction(|txs| {
+                    for (tx, batch) in txs.iter().zip(&batches) {
+                        tx.apply_batch(batch)?;
+                    }
+
+                    Ok(())
+                });
+
+                result.unwrap();
+
+                if durable {
+                    db.flush().unwrap();
+                }
+            }
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let (tx, _writer) = jamm_tx(db, tx_start, true);
+
+                for (key, value) in puts {
+                    let bucket = &buckets[partition_idx(key, buckets.len())];
+                    let bucket = tx.get_bucket(bucket.as_str()).unwrap();
+                    bucket.put(key.as_slice(), value.as_slice()).unwrap();
+                }
+                for key in deletes {
+                    let bucket = &buckets[partition_idx(key, buckets.len())];
+                    let bucket = tx.get_bucket(bucket.as_str()).unwrap();
+
+                    // NOTE: Deleting a missing key is not an error here
+                    let _ = bucket.delete(key);
+                }
+
+                tx.commit().unwrap();
+            }
+            GenericDatabase::Persy { db, partitions } => {
+                use persy::{ByteVec, PersyId, TransactionConfig};
+
+                let mut tx = db
+                    .begin_with(TransactionConfig::new().set_background_sync(!durable))
                     .unwrap();
-                if let Some(id) = read_id.next() {
-                    db.read("data", &id).unwrap()
-                } else {
-                    None
+
+                for (key, value) in puts {
+                    let (segment, index) = &partitions[partition_idx(key, partitions.len())];
+
+                    let id = tx.insert(segment, value).unwrap();
+                    tx.put::<ByteVec, PersyId>(index, ByteVec::from(key.clone()), id)
+                        .unwrap();
+                }
+                for key in deletes {
+                    let (segment, index) = &partitions[partition_idx(key, partitions.len())];
+                    let key = ByteVec::from(key.clone());
+
+                    if let Some(id) = tx.one::<ByteVec, PersyId>(index, &key).unwrap() {
+                        tx.delete(segment, &id).unwrap();
+                        tx.remove::<ByteVec, PersyId>(index, key, Some(id)).unwrap();
+                    }
                 }
+
+                let prepared = tx.prepare().unwrap();
+                prepared.commit().unwrap();
             }
-            GenericDatabase::Redb(db) => {
-                let read_txn = db.begin_read().unwrap();
-                let table = read_txn.open_table(TABLE).unwrap();
-                table.get(key).unwrap().map(|x| x.value())
+            GenericDatabase::Redb { db, tables } => {
+                use redb::Durability::{Eventual, Immediate};
+
+                let durability = if durable { Immediate } else { Eventual };
+
+                let committed = self.redb_write(db, durability, |write_txn| {
+                    for (key, value) in puts {
+                        let table = redb_table(&tables[partition_idx(key, tables.len())]);
+                        let mut table = write_txn.open_table(table)?;
+                        table.insert(key.as_slice(), value.clone())?;
+                    }
+                    for key in deletes {
+                        let table = redb_table(&tables[partition_idx(key, tables.len())]);
+                        let mut table = write_txn.open_table(table)?;
+                        table.remove(key.as_slice())?;
+                    }
+
+                    Ok(())
+                });
+
+                return committed.is_some();
             }
-        };
+        }
 
-        self.read_latency.fetch_add(
-            start.elapsed().as_micros() as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
+        true
+    }
 
-        self.read_ops
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    /// Runs `f` in a redb write transaction and commits it, returning `None` if that failed
+    ///
+    /// Writes may fail on purpose when using --redb-fail-after-writes, so failures are counted
+    /// instead of taking down the worker thread, and only logged now and then
+    fn redb_write<T>(
+        &self,
+        db: &redb::Database,
+        durability: redb::Durability,
+        f: impl FnOnce(&redb::WriteTransaction) -> Result<T, Box<dyn std::error::Error>>,
+    ) -> Option<T> {
+        let result = (|| -> Result<T, Box<dyn std::error::Error>> {
+            let mut write_txn = db.begin_write()?;
+            write_txn.set_durability(durability);
 
-        item
+            let value = f(&write_txn)?;
+            write_txn.commit()?;
+
+            Ok(value)
+        })();
+
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                let failed = self
+                    .local()
+                    .failed_writes
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                    + 1;
+
+                // NOTE: Once the backend fails, every write does, so don't flood the log
+                if failed.is_power_of_two() {
+                    log::error!("redb write failed ({failed} so far on this thread): {e}");
+                }
+
+                None
+            }
+        }
+    }
+
+    /// How read-modify-writes are made atomic, recorded in the setup record
+    /// as it differs by backend
+    pub fn rmw_isolation(&self) -> &'static str {
+        match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb { .. } => "blind_merge",
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(_) => "optimistic_transaction",
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { .. } => "write_transaction",
+            GenericDatabase::Nebari { .. } => "transaction",
+
+            // NOTE: Emulated using locks held by the benchmark
+            GenericDatabase::Fjall { .. } | GenericDatabase::Persy { .. } => "emulated_key_locks",
+            GenericDatabase::Sled { .. } => "compare_and_swap",
+            GenericDatabase::Jamm { .. } => "write_transaction",
+            GenericDatabase::Redb { .. } => "write_transaction",
+        }
+    }
+
+    /// Reads a value and writes it back modified, atomically
+    ///
+    /// Optimistic backends retry on conflict, single writer backends
+    /// simply do both in one write transaction
+    pub fn read_modify_write(&self, key: &[u8], durable: bool) {
+        let start = Instant::now();
+
+        let mut conflicts = 0;
+
+        match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                let cf = &column_families[partition_idx(key, column_families.len())];
+                let cf = db.cf_handle(cf).unwrap();
+
+                // NOTE: The merge operator does the read & modify on read or compaction,
+                // so this is a blind write, nothing is read here
+                db.merge_cf(cf, key, 1_u64.to_le_bytes()).unwrap();
+
+                if durable {
+                    db.flush_wal(true).unwrap();
+                }
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                use surrealkv::Durability::{Eventual, Immediate};
+
+                loop {
+                    let mut tx = db.begin().unwrap();
+                    tx.set_durability(if durable { Immediate } else { Eventual });
+
+                    let value = incremented(tx.get(key).unwrap().as_deref());
+                    tx.set(key, &value).unwrap();
+
+                    match pollster::block_on(tx.commit()) {
+                        Ok(()) => break,
+                        Err(e) if is_surrealkv_conflict(&e) => conflicts += 1,
+                        Err(e) => panic!("surrealkv commit failed: {e}"),
+                    }
+                }
+            }
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { env, dbs } => {
+                let db = &dbs[partition_idx(key, dbs.len())];
+
+                let mut wtxn = env.write_txn().unwrap();
+
+                let value = incremented(db.get(&wtxn, key).unwrap());
+                db.put(&mut wtxn, key, &value).unwrap();
+
+                wtxn.commit().unwrap();
+            }
+            GenericDatabase::Nebari { roots, trees } => {
+                let tx = NebariTransaction::begin(roots, trees.len(), [key]);
+
+                let mut tree = tx.tree(key);
+                let value = incremented(tree.get(key).unwrap().as_deref());
+                tree.set(key.to_vec(), value).unwrap();
+                drop(tree);
+
+                tx.commit();
+            }
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                let db = &partitions[partition_idx(key, partitions.len())];
+
+                // NOTE: The plain keyspace has no transactions, so serialize per key,
+                // which is only atomic among the benchmark's own threads (see `rmw_isolation`)
+                let _guard = self.key_locks.lock(key);
+
+                let value = incremented(db.get(key).unwrap().as_deref());
+                db.insert(key, value).unwrap();
+
+                if durable {
+                    keyspace.persist(fjall::FlushMode::SyncAll).unwrap();
+                }
+            }
+            GenericDatabase::Sled { db, trees } => {
+                let tree = &trees[partition_idx(key, trees.len())];
+
+                loop {
+                    let old = tree.get(key).unwrap();
+                    let value = incremented(old.as_deref());
+
+                    match tree.compare_and_swap(key, old, Some(value)).unwrap() {
+                        Ok(()) => break,
+                        Err(_) => conflicts += 1,
+                    }
+                }
+
+                if durable {
+                    db.flush().unwrap();
+                }
+            }
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let bucket = &buckets[partition_idx(key, buckets.len())];
+
+                let (tx, _writer) = jamm_tx(db, tx_start, true);
+                let bucket = tx.get_bucket(bucket.as_str()).unwrap();
+
+                let old = bucket.get(key).map(|item| item.kv().value().to_vec());
+                bucket.put(key, incremented(old.as_deref())).unwrap();
+
+                tx.commit().unwrap();
+            }
+            GenericDatabase::Persy { db, partitions } => {
+                use persy::{ByteVec, PersyId, TransactionConfig};
+
+                let (segment, index) = &partitions[partition_idx(key, partitions.len())];
+
+                // NOTE: Persy does not notice if a record it read was changed before commit,
+                // so serialize per key
+                let _guard = self.key_locks.lock(key);
+
+                let key = ByteVec::from(key.to_vec());
+
+                loop {
+                    let mut tx = db
+                        .begin_with(TransactionConfig::new().set_background_sync(!durable))
+                        .unwrap();
+
+                    if let Some(id) = tx.one::<ByteVec, PersyId>(index, &key).unwrap() {
+                        let value = incremented(tx.read(segment, &id).unwrap().as_deref());
+                        tx.update(segment, &id, &value).unwrap();
+                    } else {
+                        let id = tx.insert(segment, &incremented(None)).unwrap();
+                        tx.put::<ByteVec, PersyId>(index, key.clone(), id).unwrap();
+                    }
+
+                    if persy_commit(tx, &mut conflicts) {
+                        break;
+                    }
+                }
+            }
+            GenericDatabase::Redb { db, tables } => {
+                use redb::Durability::{Eventual, Immediate};
+
+                let table = redb_table(&tables[partition_idx(key, tables.len())]);
+
+                let durability = if durable { Immediate } else { Eventual };
+
+                let committed = self.redb_write(db, durability, |write_txn| {
+                    let mut table = write_txn.open_table(table)?;
+
+                    let old = table.get(key)?.map(|x| x.value());
+                    table.insert(key, incremented(old.as_deref()))?;
+
+                    Ok(())
+                });
+
+                if committed.is_none() {
+                    return;
+                }
+            }
+        }
+
+        self.local().rmw_latency.record(start.elapsed());
+
+        self.local()
+            .rmw_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        self.local()
+            .rmw_conflicts
+            .fetch_add(conflicts, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Moves `amount` from the counter of one record to another, in one transaction
+    ///
+    /// Counters wrap around, so the sum of all counters never changes
+    pub fn transfer(&self, from: &[u8], to: &[u8], amount: u64, durable: bool) {
+        // NOTE: Transfers to self are no-ops
+        if from == to {
+            return;
+        }
+
+        let start = Instant::now();
+
+        let mut aborts = 0;
+
+        let deltas = [(from, amount.wrapping_neg()), (to, amount)];
+
+        match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                // NOTE: Merges commute, so an atomic batch of merges is isolated as well
+                let mut batch = rocksdb::WriteBatch::default();
+
+                for (key, delta) in deltas {
+                    let cf = &column_families[partition_idx(key, column_families.len())];
+                    let cf = db.cf_handle(cf).unwrap();
+
+                    batch.merge_cf(cf, key, delta.to_le_bytes());
+                }
+
+                db.write(batch).unwrap();
+
+                if durable {
+                    db.flush_wal(true).unwrap();
+                }
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                use surrealkv::Durability::{Eventual, Immediate};
+
+                loop {
+                    let mut tx = db.begin().unwrap();
+                    tx.set_durability(if durable { Immediate } else { Eventual });
+
+                    for (key, delta) in deltas {
+                        let value = adjusted(tx.get(key).unwrap().as_deref(), delta);
+                        tx.set(key, &value).unwrap();
+                    }
+
+                    match pollster::block_on(tx.commit()) {
+                        Ok(()) => break,
+                        Err(e) if is_surrealkv_conflict(&e) => aborts += 1,
+                        Err(e) => panic!("surrealkv commit failed: {e}"),
+                    }
+                }
+            }
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { env, dbs } => {
+                let mut wtxn = env.write_txn().unwrap();
+
+                for (key, delta) in deltas {
+                    let db = &dbs[partition_idx(key, dbs.len())];
+
+                    let value = adjusted(db.get(&wtxn, key).unwrap(), delta);
+                    db.put(&mut wtxn, key, &value).unwrap();
+                }
+
+                wtxn.commit().unwrap();
+            }
+            GenericDatabase::Nebari { roots, trees } => {
+                let tx = NebariTransaction::begin(roots, trees.len(), [from, to]);
+
+                for (key, delta) in deltas {
+                    let mut tree = tx.tree(key);
+
+                    let value = adjusted(tree.get(key).unwrap().as_deref(), delta);
+                    tree.set(key.to_vec(), value).unwrap();
+                }
+
+                tx.commit();
+            }
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                // NOTE: The plain keyspace has no transactions, so isolate using the locks,
+                // and commit atomically using a batch
+                let _guards = self.key_locks.lock_both(from, to);
+
+                let mut batch = keyspace.batch();
+
+                for (key, delta) in deltas {
+                    let partition = &partitions[partition_idx(key, partitions.len())];
+
+                    let value = adjusted(partition.get(key).unwrap().as_deref(), delta);
+                    batch.insert(partition, key, value);
+                }
+
+                batch.commit().unwrap();
+
+                if durable {
+                    keyspace.persist(fjall::FlushMode::SyncAll).unwrap();
+                }
+            }
+            GenericDatabase::Sled { db, trees } => {
+                use sled::transaction::TransactionResult;
+                use sled::Transactional;
+
+                // NOTE: sled retries conflicting transactions internally,
+                // so count how often the closure runs
+                let attempts = std::cell::Cell::new(0);
+
+                let result: TransactionResult<()> = trees.as_slice().transaction(|txs| {
+                    attempts.set(attempts.get() + 1);
+
+                    for (key, delta) in deltas {
+                        let tx = &txs[partition_idx(key, txs.len())];
+
+                        let value = adjusted(tx.get(key)?.as_deref(), delta);
+                        tx.insert(key, value)?;
+                    }
+
+                    Ok(())
+                });
+
+                result.unwrap();
+
+                aborts = attempts.get() - 1;
+
+                if durable {
+                    db.flush().unwrap();
+                }
+            }
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let (tx, _writer) = jamm_tx(db, tx_start, true);
+
+                for (key, delta) in deltas {
+                    let bucket = &buckets[partition_idx(key, buckets.len())];
+                    let bucket = tx.get_bucket(bucket.as_str()).unwrap();
+
+                    let old = bucket.get(key).map(|item| item.kv().value().to_vec());
+                    bucket.put(key, adjusted(old.as_deref(), delta)).unwrap();
+                }
+
+                tx.commit().unwrap();
+            }
+            GenericDatabase::Persy { db, partitions } => {
+                use persy::{ByteVec, PersyId, TransactionConfig};
+
+                // NOTE: Persy does not notice if a record it read was changed before commit,
+                // so isolate using the locks
+                let _guards = self.key_locks.lock_both(from, to);
+
+                loop {
+                    let mut tx = db
+                        .begin_with(TransactionConfig::new().set_background_sync(!durable))
+                        .unwrap();
+
+                    for (key, delta) in deltas {
+                        let (segment, index) = &partitions[partition_idx(key, partitions.len())];
+
+                        let key = ByteVec::from(key.to_vec());
+
+                        if let Some(id) = tx.one::<ByteVec, PersyId>(index, &key).unwrap() {
+                            let value = adjusted(tx.read(segment, &id).unwrap().as_deref(), delta);
+                            tx.update(segment, &id, &value).unwrap();
+                        } else {
+                            let id = tx.insert(segment, &adjusted(None, delta)).unwrap();
+                            tx.put::<ByteVec, PersyId>(index, key, id).unwrap();
+                        }
+                    }
+
+                    if persy_commit(tx, &mut aborts) {
+                        break;
+                    }
+                }
+            }
+            GenericDatabase::Redb { db, tables } => {
+                use redb::Durability::{Eventual, Immediate};
+
+                let durability = if durable { Immediate } else { Eventual };
+
+                let committed = self.redb_write(db, durability, |write_txn| {
+                    for (key, delta) in deltas {
+                        let table = redb_table(&tables[partition_idx(key, tables.len())]);
+                        let mut table = write_txn.open_table(table)?;
+
+                        let old = table.get(key)?.map(|x| x.value());
+                        table.insert(key, adjusted(old.as_deref(), delta))?;
+                    }
+
+                    Ok(())
+                });
+
+                if committed.is_none() {
+                    return;
+                }
+            }
+        }
+
+        self.local().tx_latency.record(start.elapsed());
+
+        self.local()
+            .tx_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        self.local()
+            .tx_aborts
+            .fetch_add(aborts, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Reads all records in `start..end`, returning their keys
+    ///
+    /// Keys are spread over partitions by hash, so every partition is scanned
+    pub fn scan(&self, start: &[u8], end: &[u8]) -> Vec<Vec<u8>> {
+        let begin = Instant::now();
+
+        let keys = match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => column_families
+                .iter()
+                .flat_map(|cf| {
+                    let cf = db.cf_handle(cf).unwrap();
+
+                    db.iterator_cf(
+                        cf,
+                        rocksdb::IteratorMode::From(start, rocksdb::Direction::Forward),
+                    )
+                    .map(Result::unwrap)
+                    .take_while(|(key, _)| &**key < end)
+                    .map(|(key, _)| key.to_vec())
+                })
+                .collect(),
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                let mut tx = db.begin_with_mode(surrealkv::Mode::ReadOnly).unwrap();
+
+                tx.scan(start..end, None)
+                    .unwrap()
+                    .into_iter()
+                    .map(|(key, ..)| key.to_vec())
+                    .collect()
+            }
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { dbs, env } => {
+                use std::ops::Bound::{Excluded, Included};
+
+                let rtxn = env.read_txn().unwrap();
+
+                dbs.iter()
+                    .flat_map(|db| {
+                        db.range(&rtxn, &(Included(start), Excluded(end)))
+                            .unwrap()
+                            .map(Result::unwrap)
+                            .map(|(key, _)| key.to_vec())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            }
+
+            GenericDatabase::Nebari { roots: _, trees } => trees
+                .iter()
+                .flat_map(|tree| tree.get_range(&(start..end)).unwrap())
+                .map(|(key, _)| key.to_vec())
+                .collect(),
+            GenericDatabase::Fjall {
+                keyspace: _,
+                partitions,
+            } => partitions
+                .iter()
+                .flat_map(|db| db.range(start..end).map(Result::unwrap))
+                .map(|(key, _)| key.to_vec())
+                .collect(),
+            GenericDatabase::Sled { db: _, trees } => trees
+                .iter()
+                .flat_map(|tree| tree.range(start..end).map(Result::unwrap))
+                .map(|(key, _)| key.to_vec())
+                .collect(),
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let (tx, _) = jamm_tx(db, tx_start, false);
+
+                buckets
+                    .iter()
+                    .flat_map(|bucket| {
+                        let bucket = tx.get_bucket(bucket.as_str()).unwrap();
+
+                        bucket
+                            .range(start..end)
+                            .map(|item| item.kv().key().to_vec())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            }
+            GenericDatabase::Persy { db, partitions } => {
+                use persy::{ByteVec, PersyId};
+
+                let start = ByteVec::from(start.to_vec());
+                let end = ByteVec::from(end.to_vec());
+
+                partitions
+                    .iter()
+                    .flat_map(|(segment, index)| {
+                        db.range::<ByteVec, PersyId, _>(index, start.clone()..end.clone())
+                            .unwrap()
+                            .filter_map(|(key, ids)| {
+                                ids.into_iter()
+                                    .find_map(|id| db.read(segment, &id).unwrap())
+                                    .map(|_| key.to_vec())
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            }
+            GenericDatabase::Redb { db, tables } => {
+                let read_txn = db.begin_read().unwrap();
+
+                tables
+                    .iter()
+                    .flat_map(|table| {
+                        let table = read_txn.open_table(redb_table(table)).unwrap();
+
+                        table
+                            .range(start..end)
+                            .unwrap()
+                            .map(Result::unwrap)
+                            .map(|(key, _)| key.value().to_vec())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            }
+        };
+
+        self.local().scan_latency.record(begin.elapsed());
+
+        self.local()
+            .scan_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        keys
+    }
+
+    /// Deletes all records in `start..end`
+    ///
+    /// Uses native range deletes where the backend has them,
+    /// otherwise deletes the keys one by one in a single batch or transaction
+    pub fn delete_range(&self, start: &[u8], end: &[u8], durable: bool) {
+        let begin = Instant::now();
+
+        match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                for cf in column_families {
+                    let cf = db.cf_handle(cf).unwrap();
+                    db.delete_range_cf(cf, start, end).unwrap();
+                }
+
+                if durable {
+                    db.flush_wal(true).unwrap();
+                }
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                use surrealkv::Durability::{Eventual, Immediate};
+
+                let mut tx = db.begin().unwrap();
+                tx.set_durability(if durable { Immediate } else { Eventual });
+
+                for (key, ..) in tx.scan(start..end, None).unwrap() {
+                    tx.delete(&key).unwrap();
+                }
+
+                pollster::block_on(tx.commit()).unwrap();
+            }
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { dbs, env } => {
+                use std::ops::Bound::{Excluded, Included};
+
+                let mut wtxn = env.write_txn().unwrap();
+
+                for db in dbs {
+                    db.delete_range(&mut wtxn, &(Included(start), Excluded(end)))
+                        .unwrap();
+                }
+
+                wtxn.commit().unwrap();
+            }
+
+            GenericDatabase::Nebari { roots: _, trees } => {
+                for tree in trees {
+                    for (key, _) in tree.get_range(&(start..end)).unwrap() {
+                        tree.remove(&key).unwrap();
+                    }
+                }
+            }
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                let mut batch = keyspace.batch();
+
+                for partition in partitions {
+                    for item in partition.range(start..end) {
+                        let (key, _) = item.unwrap();
+                        batch.remove(partition, key);
+                    }
+                }
+
+                batch.commit().unwrap();
+
+                if durable {
+                    keyspace.persist(fjall::FlushMode::SyncAll).unwrap();
+                }
+            }
+            GenericDatabase::Sled { db, trees } => {
+                for tree in trees {
+                    let mut batch = sled::Batch::default();
+
+                    for key in tree.range(start..end).keys() {
+                        batch.remove(key.unwrap());
+                    }
+
+                    tree.apply_batch(batch).unwrap();
+                }
+
+                if durable {
+                    db.flush().unwrap();
+                }
+            }
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let (tx, _writer) = jamm_tx(db, tx_start, true);
+
+                for bucket in buckets {
+                    let bucket = tx.get_bucket(bucket.as_str()).unwrap();
+
+                    let keys = bucket
+                        .range(start..end)
+                        .map(|item| item.kv().key().to_vec())
+                        .collect::<Vec<_>>();
+
+                    for key in keys {
+                        bucket.delete(key).unwrap();
+                    }
+                }
+
+                tx.commit().unwrap();
+            }
+            GenericDatabase::Persy { db, partitions } => {
+                use persy::{ByteVec, PersyId, TransactionConfig};
+
+                let start = ByteVec::from(start.to_vec());
+                let end = ByteVec::from(end.to_vec());
+
+                let mut tx = db
+                    .begin_with(TransactionConfig::new().set_background_sync(!durable))
+                    .unwrap();
+
+                for (segment, index) in partitions {
+                    let items = db
+                        .range::<ByteVec, PersyId, _>(index, start.clone()..end.clone())
+                        .unwrap()
+                        .collect::<Vec<_>>();
+
+                    for (key, ids) in items {
+                        for id in ids {
+                            tx.delete(segment, &id).unwrap();
+                            tx.remove::<ByteVec, PersyId>(index, key.clone(), Some(id))
+                                .unwrap();
+                        }
+                    }
+                }
+
+                tx.prepare().unwrap().commit().unwrap();
+            }
+            GenericDatabase::Redb { db, tables } => {
+                use redb::Durability::{Eventual, Immediate};
+
+                let durability = if durable { Immediate } else { Eventual };
+
+                let committed = self.redb_write(db, durability, |write_txn| {
+                    for table in tables {
+                        let mut table = write_txn.open_table(redb_table(table))?;
+                        table.retain_in(start..end, |_, _| false)?;
+                    }
+
+                    Ok(())
+                });
+
+                if committed.is_none() {
+                    return;
+                }
+            }
+        }
+
+        self.local().delete_latency.record(begin.elapsed());
+
+        self.local()
+            .delete_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Deletes the first record in `start..end` and returns its key, like popping a queue
+    ///
+    /// Backends without transactions serialize pops using a lock
+    pub fn pop_first(&self, start: &[u8], end: &[u8], durable: bool) -> Option<Vec<u8>> {
+        let begin = Instant::now();
+
+        let mut conflicts = 0;
+
+        let key = match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                let _guard = self.key_locks.lock(start);
+
+                // NOTE: Keys are spread over partitions, so find the first one of all
+                let first = column_families
+                    .iter()
+                    .filter_map(|cf| {
+                        let handle = db.cf_handle(cf).unwrap();
+
+                        db.iterator_cf(
+                            handle,
+                            rocksdb::IteratorMode::From(start, rocksdb::Direction::Forward),
+                        )
+                        .map(Result::unwrap)
+                        .next()
+                        .filter(|(key, _)| &**key < end)
+                        .map(|(key, _)| (key, cf))
+                    })
+                    .min();
+
+                first.map(|(key, cf)| {
+                    db.delete_cf(db.cf_handle(cf).unwrap(), &key).unwrap();
+
+                    if durable {
+                        db.flush_wal(true).unwrap();
+                    }
+
+                    key.to_vec()
+                })
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => loop {
+                use surrealkv::Durability::{Eventual, Immediate};
+
+                let mut tx = db.begin().unwrap();
+                tx.set_durability(if durable { Immediate } else { Eventual });
+
+                let Some((key, ..)) = tx.scan(start..end, Some(1)).unwrap().into_iter().next()
+                else {
+                    break None;
+                };
+
+                tx.delete(&key).unwrap();
+
+                match pollster::block_on(tx.commit()) {
+                    Ok(()) => break Some(key),
+                    Err(e) if is_surrealkv_conflict(&e) => conflicts += 1,
+                    Err(e) => panic!("surrealkv commit failed: {e}"),
+                }
+            },
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { dbs, env } => {
+                use std::ops::Bound::{Excluded, Included};
+
+                let mut wtxn = env.write_txn().unwrap();
+
+                let first = dbs
+                    .iter()
+                    .filter_map(|db| {
+                        let mut range = db.range(&wtxn, &(Included(start), Excluded(end))).unwrap();
+                        range.next().map(|item| (item.unwrap().0.to_vec(), db))
+                    })
+                    .min_by(|a, b| a.0.cmp(&b.0));
+
+                if let Some((key, db)) = &first {
+                    db.delete(&mut wtxn, key).unwrap();
+                }
+
+                wtxn.commit().unwrap();
+
+                first.map(|(key, _)| key)
+            }
+
+            GenericDatabase::Nebari { roots: _, trees } => {
+                let _guard = self.key_locks.lock(start);
+
+                let first = trees
+                    .iter()
+                    .filter_map(|tree| {
+                        use nebari::tree::ScanEvaluation;
+
+                        let mut first = None;
+
+                        // NOTE: Stop at the first key, without reading its value
+                        tree.scan::<std::convert::Infallible, _, _, _, _>(
+                            &(start..end),
+                            true,
+                            |_, _, _| ScanEvaluation::ReadData,
+                            |key, _| {
+                                first = Some(key.to_vec());
+                                ScanEvaluation::Stop
+                            },
+                            |_, _, _| Ok(()),
+                        )
+                        .unwrap();
+
+                        first.map(|key| (key, tree))
+                    })
+                    .min_by(|a, b| a.0.cmp(&b.0));
+
+                first.map(|(key, tree)| {
+                    tree.remove(&key).unwrap();
+                    key
+                })
+            }
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                let _guard = self.key_locks.lock(start);
+
+                let first = partitions
+                    .iter()
+                    .filter_map(|partition| {
+                        let item = partition.range(start..end).next()?;
+                        Some((item.unwrap().0, partition))
+                    })
+                    .min_by(|a, b| a.0.cmp(&b.0));
+
+                first.map(|(key, partition)| {
+                    partition.remove(&*key).unwrap();
+
+                    if durable {
+                        keyspace.persist(fjall::FlushMode::SyncAll).unwrap();
+                    }
+
+                    key.to_vec()
+                })
+            }
+            GenericDatabase::Sled { db, trees } => {
+                let _guard = self.key_locks.lock(start);
+
+                let first = trees
+                    .iter()
+                    .filter_map(|tree| {
+                        let (key, _) = tree.range(start..end).next()?.unwrap();
+                        Some((key, tree))
+                    })
+                    .min_by(|a, b| a.0.cmp(&b.0));
+
+                first.map(|(key, tree)| {
+                    tree.remove(&key).unwrap();
+
+                    if durable {
+                        db.flush().unwrap();
+                    }
+
+                    key.to_vec()
+                })
+            }
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let (tx, _writer) = jamm_tx(db, tx_start, true);
+
+                let first = buckets
+                    .iter()
+                    .filter_map(|name| {
+                        let bucket = tx.get_bucket(name.as_str()).unwrap();
+
+                        let key = bucket.range(start..end).next()?.kv().key().to_vec();
+                        Some((key, name))
+                    })
+                    .min();
+
+                if let Some((key, name)) = &first {
+                    let bucket = tx.get_bucket(name.as_str()).unwrap();
+                    bucket.delete(key).unwrap();
+                }
+
+                tx.commit().unwrap();
+
+                first.map(|(key, _)| key)
+            }
+            GenericDatabase::Persy { db, partitions } => {
+                use persy::{ByteVec, PersyId, TransactionConfig};
+
+                let start = ByteVec::from(start.to_vec());
+                let end = ByteVec::from(end.to_vec());
+
+                loop {
+                    let result = (|| -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+                        let mut tx =
+                            db.begin_with(TransactionConfig::new().set_background_sync(!durable))?;
+
+                        let mut first: Option<(ByteVec, Vec<PersyId>, &String, &String)> = None;
+
+                        for (segment, index) in partitions {
+                            let item = tx
+                                .range::<ByteVec, PersyId, _>(index, start.clone()..end.clone())?
+                                .next();
+
+                            if let Some((key, ids)) = item {
+                                if first.as_ref().is_none_or(|(first, ..)| key < *first) {
+                                    first = Some((key, ids.collect::<Vec<_>>(), segment, index));
+                                }
+                            }
+                        }
+
+                        let Some((key, ids, segment, index)) = first else {
+                            return Ok(None);
+                        };
+
+                        for id in ids {
+                            tx.delete(segment, &id)?;
+                            tx.remove::<ByteVec, PersyId>(index, key.clone(), Some(id))?;
+                        }
+
+                        tx.prepare()?.commit()?;
+
+                        Ok(Some(key.to_vec()))
+                    })();
+
+                    // NOTE: Fails on delete or prepare if another consumer popped the same item
+                    match result {
+                        Ok(key) => break key,
+                        Err(e) => {
+                            conflicts += 1;
+                            assert!(
+                                conflicts <= PERSY_MAX_RETRIES,
+                                "persy transaction failed {conflicts} times: {e}"
+                            );
+                        }
+                    }
+                }
+            }
+            GenericDatabase::Redb { db, tables } => {
+                use redb::Durability::{Eventual, Immediate};
+
+                let durability = if durable { Immediate } else { Eventual };
+
+                let popped = self.redb_write(db, durability, |write_txn| {
+                    let mut first: Option<(Vec<u8>, &String)> = None;
+
+                    for name in tables {
+                        let table = write_txn.open_table(redb_table(name))?;
+
+                        let item = table.range(start..end)?.next().transpose()?;
+
+                        if let Some((key, _)) = item {
+                            let key = key.value().to_vec();
+
+                            if first.as_ref().is_none_or(|(first, _)| key < *first) {
+                                first = Some((key, name));
+                            }
+                        }
+                    }
+
+                    if let Some((key, name)) = &first {
+                        let mut table = write_txn.open_table(redb_table(name))?;
+                        table.remove(key.as_slice())?;
+                    }
+
+                    Ok(first.map(|(key, _)| key))
+                });
+
+                popped?
+            }
+        };
+
+        self.local()
+            .pop_conflicts
+            .fetch_add(conflicts, std::sync::atomic::Ordering::Relaxed);
+
+        // NOTE: Finding the queue empty is not a pop, so it only counts as an empty one
+        if key.is_none() {
+            self.local()
+                .empty_pops
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+            return None;
+        }
+
+        self.local().pop_latency.record(begin.elapsed());
+
+        self.local()
+            .pop_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        key
+    }
+
+    /// What `close` does, recorded next to how long it took,
+    /// as not every backend has the same work left to do
+    pub fn close_work(&self) -> &'static str {
+        match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb { .. } => "flush_memtables",
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(_) => "close",
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { .. } => "sync",
+
+            // NOTE: Memtables are not flushed, only the journal is synced
+            GenericDatabase::Fjall { .. } => "sync_journal",
+            GenericDatabase::Sled { .. } => "flush",
+            GenericDatabase::Nebari { .. }
+            | GenericDatabase::Jamm { .. }
+            | GenericDatabase::Persy { .. }
+            | GenericDatabase::Redb { .. } => "drop",
+        }
+    }
+
+    /// Flushes all pending writes to disk and closes the database
+    ///
+    /// Other clones keep the backend open, so this needs to be the last one
+    pub fn close(self) {
+        match self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                for cf in &column_families {
+                    db.flush_cf(db.cf_handle(cf).unwrap()).unwrap();
+                }
+                db.flush_wal(true).unwrap();
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                pollster::block_on(db.close()).unwrap();
+            }
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { env, dbs: _ } => {
+                env.force_sync().unwrap();
+            }
+
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                // NOTE: This leaves the memtables to be recovered from the journal,
+                // unlike RocksDB, which flushes them (see `close_work`)
+                keyspace.persist(fjall::FlushMode::SyncAll).unwrap();
+
+                // NOTE: Partitions hold on to the keyspace, so drop them first
+                drop(partitions);
+            }
+            GenericDatabase::Sled { db, trees: _ } => {
+                db.flush().unwrap();
+            }
+
+            // NOTE: Dropping the last handle closes these
+            GenericDatabase::Nebari { .. }
+            | GenericDatabase::Jamm { .. }
+            | GenericDatabase::Persy { .. }
+            | GenericDatabase::Redb { .. } => {}
+        }
+    }
+
+    /// Zeroes the op and latency counters of all threads, e.g. when a new phase starts
+    ///
+    /// `write_bytes` is kept, it tracks the dataset size
+    pub fn reset_counters(&self) {
+        for counters in self.counters.iter() {
+            counters.reset();
+        }
+    }
+
+    /// Handle that counts into the slot of worker thread `idx`
+    pub fn for_thread(&self, idx: usize) -> Self {
+        Self {
+            slot: idx.min(self.counters.len() - 1),
+            ..self.clone()
+        }
+    }
+
+    /// Sums a counter over all threads
+    pub fn total(&self, counter: impl Fn(&Counters) -> &AtomicU64) -> u64 {
+        use std::sync::atomic::Ordering::Relaxed;
+
+        self.counters.iter().map(|x| counter(x).load(Relaxed)).sum()
+    }
+
+    /// Merges the latencies of all threads
+    pub fn merged_latency(&self, latency: impl Fn(&Counters) -> &Latency) -> Latency {
+        let merged = Latency::default();
+
+        for counters in self.counters.iter() {
+            merged.merge_from(latency(counters));
+        }
+
+        merged
+    }
+
+    fn local(&self) -> &Counters {
+        &self.counters[self.slot]
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let start = Instant::now();
+
+        let item = self.get_untimed(key);
+
+        self.local().read_latency.record(start.elapsed());
+
+        self.local()
+            .read_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        self.local().value_read_bytes.fetch_add(
+            item.as_ref().map_or(0, |x| x.len() as u64),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+
+        item
+    }
+
+    /// Looks up many keys at once, using a single snapshot or transaction where the backend supports it
+    ///
+    /// Each key counts as one read, so `read_latency` stays per key
+    pub fn multi_get(&self, keys: &[Vec<u8>]) -> Vec<Option<Vec<u8>>> {
+        let start = Instant::now();
+
+        let items: Vec<_> = match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => db
+                .multi_get_cf(keys.iter().map(|key| {
+                    let cf = &column_families[partition_idx(key, column_families.len())];
+                    (db.cf_handle(cf).unwrap(), key)
+                }))
+                .into_iter()
+                .map(Result::unwrap)
+                .collect(),
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                let mut tx = db.begin_with_mode(surrealkv::Mode::ReadOnly).unwrap();
+                keys.iter().map(|key| tx.get(key).unwrap()).collect()
+            }
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { dbs, env } => {
+                let rtxn = env.read_txn().unwrap();
+
+                keys.iter()
+                    .map(|key| {
+                        let db = &dbs[partition_idx(key, dbs.len())];
+                        db.get(&rtxn, key).unwrap().map(|x| x.to_vec())
+                    })
+                    .collect()
+            }
+
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            } => {
+                // NOTE: Read all partitions at the same point in time
+                let instant = keyspace.instant();
+
+                keys.iter()
+                    .map(|key| {
+                        let db = &partitions[partition_idx(key, partitions.len())];
+                        db.snapshot_at(instant)
+                            .get(key)
+                            .unwrap()
+                            .map(|x| x.to_vec())
+                    })
+                    .collect()
+            }
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let (tx, _) = jamm_tx(db, tx_start, false);
+
+                keys.iter()
+                    .map(|key| {
+                        let bucket = &buckets[partition_idx(key, buckets.len())];
+
+                        // NOTE: A missing bucket has no keys, buckets are only created when opening
+                        let bucket = tx.get_bucket(bucket.as_str()).ok()?;
+                        bucket.get(key).map(|item| item.kv().value().into())
+                    })
+                    .collect()
+            }
+            GenericDatabase::Redb { db, tables } => {
+                let read_txn = db.begin_read().unwrap();
+
+                keys.iter()
+                    .map(|key| {
+                        let table = redb_table(&tables[partition_idx(key, tables.len())]);
+                        let table = read_txn.open_table(table).unwrap();
+                        table.get(key.as_slice()).unwrap().map(|x| x.value())
+                    })
+                    .collect()
+            }
+
+            // NOTE: No batched reads, so just look up one by one
+            GenericDatabase::Nebari { roots: _, trees } => {
+                let mut found = std::collections::HashMap::new();
+
+                for (idx, tree) in trees.iter().enumerate() {
+                    // NOTE: Keys have to be sorted, and missing keys are left out of the results
+                    let mut tree_keys = keys
+                        .iter()
+                        .map(Vec::as_slice)
+                        .filter(|key| partition_idx(key, trees.len()) == idx)
+                        .collect::<Vec<_>>();
+                    tree_keys.sort_unstable();
+                    tree_keys.dedup();
+
+                    if !tree_keys.is_empty() {
+                        found.extend(tree.get_multiple(tree_keys.into_iter()).unwrap());
+                    }
+                }
+
+                keys.iter()
+                    .map(|key| found.get(key.as_slice()).map(|x| x.to_vec()))
+                    .collect()
+            }
+            GenericDatabase::Sled { .. } | GenericDatabase::Persy { .. } => {
+                keys.iter().map(|key| self.get_untimed(key)).collect()
+            }
+        };
+
+        let elapsed = start.elapsed();
+
+        self.local()
+            .read_latency
+            .record_many(elapsed, keys.len() as u64);
+
+        self.local()
+            .read_ops
+            .fetch_add(keys.len() as u64, std::sync::atomic::Ordering::Relaxed);
+
+        self.local().multi_get_latency.record(elapsed);
+
+        self.local()
+            .multi_get_ops
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        self.local().value_read_bytes.fetch_add(
+            items.iter().flatten().map(|x| x.len() as u64).sum(),
+            std::sync::atomic::Ordering::Relaxed,
+        );
+
+        items
+    }
+
+    fn get_untimed(&self, key: &[u8]) -> Option<Vec<u8>> {
+        match &self.inner {
+            #[cfg(feature = "rocksdb")]
+            GenericDatabase::RocksDb {
+                db,
+                column_families,
+            } => {
+                let cf = &column_families[partition_idx(key, column_families.len())];
+                let cf = db.cf_handle(cf).unwrap();
+                db.get_cf(cf, key).unwrap()
+            }
+
+            #[cfg(feature = "surrealkv")]
+            GenericDatabase::SurrealKv(db) => {
+                let mut tx = db.begin_with_mode(surrealkv::Mode::ReadOnly).unwrap();
+                tx.get(key).unwrap()
+            }
+
+            #[cfg(feature = "heed")]
+            GenericDatabase::Heed { dbs, env } => {
+                let db = &dbs[partition_idx(key, dbs.len())];
+                let rtxn = env.read_txn().unwrap();
+                let ret = db.get(&rtxn, key).unwrap();
+                ret.map(|x| x.to_vec())
+            }
+
+            GenericDatabase::Nebari { roots: _, trees } => {
+                let tree = &trees[partition_idx(key, trees.len())];
+                let item = tree.get(key).unwrap();
+                item.map(|x| x.to_vec())
+            }
+            GenericDatabase::Fjall {
+                keyspace: _,
+                partitions,
+            } => {
+                let db = &partitions[partition_idx(key, partitions.len())];
+                db.get(key).unwrap().map(|x| x.to_vec())
+            }
+            GenericDatabase::Sled { db: _, trees } => {
+                let tree = &trees[partition_idx(key, trees.len())];
+                tree.get(key).unwrap().map(|x| x.to_vec())
+            }
+            // GenericDatabase::Bloodstone(db) => db.get(key).unwrap().map(|x| x.to_vec()),
+            GenericDatabase::Jamm {
+                db,
+                buckets,
+                tx_start,
+            } => {
+                let bucket = &buckets[partition_idx(key, buckets.len())];
+
+                let (tx, _) = jamm_tx(db, tx_start, false);
+                let bucket = tx.get_bucket(bucket.as_str()).ok()?;
+                bucket.get(key).map(|item| item.kv().value().into())
+            }
+            GenericDatabase::Persy { db, partitions } => {
+                let (segment, index) = &partitions[partition_idx(key, partitions.len())];
+
+                let key = persy::ByteVec::from(key.to_vec());
+
+                let mut read_id = db
+                    .get::<persy::ByteVec, persy::PersyId>(index, &key)
+                    .unwrap();
+                if let Some(id) = read_id.next() {
+                    db.read(segment, &id).unwrap()
+                } else {
+                    None
+                }
+            }
+            GenericDatabase::Redb { db, tables } => {
+                let table = redb_table(&tables[partition_idx(key, tables.len())]);
+
+                let read_txn = db.begin_read().unwrap();
+                let table = read_txn.open_table(table).unwrap();
+                table.get(key).unwrap().map(|x| x.value())
+            }
+        }
     }
 }
@@ -0,0 +1,81 @@
+use std::path::Path;
+
+/// Sectors in `/proc/diskstats` are always 512 bytes, whatever the device uses
+const SECTOR_SIZE: u64 = 512;
+
+/// I/O of the block device backing the data directory, from `/proc/diskstats`
+///
+/// Unlike per-process counters, this includes kernel writeback of mmap'd files,
+/// but also I/O of any other process using the same device
+#[derive(Clone, Debug, Default)]
+pub struct DiskStats {
+    pub device: String,
+    pub reads: u64,
+    pub read_bytes: u64,
+    pub read_ms: u64,
+    pub writes: u64,
+    pub write_bytes: u64,
+    pub write_ms: u64,
+    pub io_ms: u64,
+}
+
+impl DiskStats {
+    /// Major and minor number of the device `path` lives on
+    #[cfg(unix)]
+    pub fn device_of(path: &Path) -> Option<(u64, u64)> {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = std::fs::metadata(path).ok()?.dev();
+
+        // NOTE: Same encoding as glibc's major() and minor()
+        let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+        let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+
+        Some((major, minor))
+    }
+
+    #[cfg(not(unix))]
+    pub fn device_of(_: &Path) -> Option<(u64, u64)> {
+        None
+    }
+
+    /// Returns `None` if the device is not listed, e.g. for overlay or tmpfs mounts
+    pub fn read((major, minor): (u64, u64)) -> Option<Self> {
+        let text = std::fs::read_to_string("/proc/diskstats").ok()?;
+
+        text.lines().find_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+
+            if fields.len() < 13 || fields[0].parse() != Ok(major) || fields[1].parse() != Ok(minor)
+            {
+                return None;
+            }
+
+            let field = |idx: usize| fields[idx].parse::<u64>().ok();
+
+            Some(Self {
+                device: fields[2].to_owned(),
+                reads: field(3)?,
+                read_bytes: field(5)? * SECTOR_SIZE,
+                read_ms: field(6)?,
+                writes: field(7)?,
+                write_bytes: field(9)? * SECTOR_SIZE,
+                write_ms: field(10)?,
+                io_ms: field(12)?,
+            })
+        })
+    }
+
+    /// What happened on the device between `prev` and `self`
+    pub fn delta_json(&self, prev: &Self) -> serde_json::Value {
+        serde_json::json!({
+            "reads": self.reads.saturating_sub(prev.reads),
+            "read_bytes": self.read_bytes.saturating_sub(prev.read_bytes),
+            "read_ms": self.read_ms.saturating_sub(prev.read_ms),
+            "writes": self.writes.saturating_sub(prev.writes),
+            "write_bytes": self.write_bytes.saturating_sub(prev.write_bytes),
+            "write_ms": self.write_ms.saturating_sub(prev.write_ms),
+            "io_ms": self.io_ms.saturating_sub(prev.io_ms),
+        })
+    }
+}
@@ -0,0 +1,91 @@
+use rand::{distributions::Distribution, Rng};
+use rand_distr::Zipf;
+use rust_storage_bench::{Args, KeyDistribution, Workload};
+
+/// Picks which of the existing records an operation goes to
+///
+/// Records are numbered `0..count`, in insertion order
+pub struct KeyChooser {
+    distribution: KeyDistribution,
+    zipf_theta: f64,
+    hotspot_fraction: f64,
+    hotspot_ops_fraction: f64,
+
+    /// Zipfian distribution for the last seen record count
+    zipf: Option<(u64, Zipf<f64>)>,
+
+    /// Next record of the sequential distribution
+    cursor: u64,
+}
+
+impl KeyChooser {
+    pub fn new(args: &Args, workload: Workload) -> Self {
+        Self {
+            distribution: args
+                .key_distribution
+                .unwrap_or(workload.default_key_distribution()),
+            zipf_theta: args.zipf_theta,
+            hotspot_fraction: args.hotspot_fraction.clamp(0.0, 1.0),
+            hotspot_ops_fraction: args.hotspot_ops_fraction.clamp(0.0, 1.0),
+            zipf: None,
+            cursor: 0,
+        }
+    }
+
+    /// Zipfian rank in `0..count`, 0 being the most popular
+    fn zipf_rank<R: Rng>(&mut self, rng: &mut R, count: u64) -> u64 {
+        // NOTE: Inserts change the record count, so rebuild the distribution lazily
+        if !matches!(&self.zipf, Some((n, _)) if *n == count) {
+            let zipf = Zipf::new(count, self.zipf_theta).unwrap();
+            self.zipf = Some((count, zipf));
+        }
+
+        let (_, zipf) = self.zipf.as_ref().unwrap();
+
+        // NOTE: Zipf samples whole numbers from 1..=count, as floats
+        zipf.sample(rng) as u64 - 1
+    }
+
+    pub fn next<R: Rng>(&mut self, rng: &mut R, count: u64) -> u64 {
+        if count == 0 {
+            return 0;
+        }
+
+        match self.distribution {
+            KeyDistribution::Uniform => rng.gen_range(0..count),
+            KeyDistribution::Zipfian => self.zipf_rank(rng, count),
+            KeyDistribution::ScrambledZipfian => {
+                let rank = self.zipf_rank(rng, count);
+                scramble(rank) % count
+            }
+            KeyDistribution::Latest => count - 1 - self.zipf_rank(rng, count),
+            KeyDistribution::Newest => count - 1,
+            KeyDistribution::Hotspot => {
+                let hot_keys = ((count as f64 * self.hotspot_fraction) as u64).clamp(1, count);
+
+                if hot_keys == count || rng.gen_bool(self.hotspot_ops_fraction) {
+                    rng.gen_range(0..hot_keys)
+                } else {
+                    rng.gen_range(hot_keys..count)
+                }
+            }
+            KeyDistribution::Sequential => {
+                let x = self.cursor % count;
+                self.cursor += 1;
+                x
+            }
+        }
+    }
+}
+
+/// Spreads zipfian ranks over the keyspace (FNV-1a)
+fn scramble(rank: u64) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for byte in rank.to_le_bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    hash
+}
@@ -0,0 +1,112 @@
+use rust_storage_bench::{Args, KeyOrder};
+
+/// Records of each user live in their own range of logical ids
+const USER_SHIFT: u32 = 40;
+
+/// Time series points live above all record ids
+const SERIES_FLAG: u64 = 1 << 63;
+
+/// Queue items live between record ids and time series points
+const QUEUE_FLAG: u64 = 1 << 62;
+
+/// Secondary index entries live between record ids and queue items
+const INDEX_FLAG: u64 = 1 << 61;
+
+/// Turns logical record ids into keys of the requested size
+#[derive(Copy, Clone)]
+pub struct KeyGenerator {
+    min_size: usize,
+    max_size: usize,
+    order: KeyOrder,
+}
+
+impl KeyGenerator {
+    pub fn new(args: &Args) -> Self {
+        let min_size = args.key_size as usize;
+        let max_size = args.key_size_max.map(usize::from).unwrap_or(min_size);
+
+        assert!(
+            max_size >= min_size,
+            "--key-size-max must not be smaller than --key-size"
+        );
+
+        Self {
+            min_size,
+            max_size,
+            order: args.key_order,
+        }
+    }
+
+    /// Key of the `seq`-th record of a user
+    ///
+    /// The first 8 bytes are unique per record, the rest is padding
+    pub fn key(&self, user: u8, seq: u64) -> Vec<u8> {
+        let id = (u64::from(user) << USER_SHIFT) | seq;
+
+        let prefix = match self.order {
+            KeyOrder::Ordered => id,
+            KeyOrder::Random => mix(id),
+        };
+
+        self.sized_key(id, prefix)
+    }
+
+    /// Key of a time series point, always ordered by time
+    pub fn series_key(&self, series: u8, ts: u64) -> Vec<u8> {
+        let id = u64::from_be_bytes(series_prefix(series, ts));
+        self.sized_key(id, id)
+    }
+
+    /// Key of the `seq`-th item pushed to the queue
+    pub fn queue_key(&self, seq: u64) -> Vec<u8> {
+        let id = u64::from_be_bytes(queue_prefix(seq));
+        self.sized_key(id, id)
+    }
+
+    fn sized_key(&self, id: u64, prefix: u64) -> Vec<u8> {
+        let size = if self.min_size == self.max_size {
+            self.min_size
+        } else {
+            // NOTE: Derive the size from the id, so the same record always gets the same key
+            let range = (self.max_size - self.min_size + 1) as u64;
+            self.min_size + (mix(!id) % range) as usize
+        };
+
+        let mut key = vec![0; size];
+        key[..8].copy_from_slice(&prefix.to_be_bytes());
+        key
+    }
+}
+
+/// Sorts before all keys of points at or after `ts`, so it can be used as a range bound
+pub fn series_prefix(series: u8, ts: u64) -> [u8; 8] {
+    debug_assert!(ts < 1 << USER_SHIFT, "time series timestamp out of range");
+    (SERIES_FLAG | (u64::from(series) << USER_SHIFT) | ts).to_be_bytes()
+}
+
+/// Sorts before the keys of all queue items from `seq` on
+///
+/// `queue_prefix(u64::MAX)` is past the end of the queue
+pub fn queue_prefix(seq: u64) -> [u8; 8] {
+    (QUEUE_FLAG | seq.min(QUEUE_FLAG - 1)).to_be_bytes()
+}
+
+/// Sorts before all entries of `index` with secondary key `attr` or greater
+pub fn index_prefix(index: u8, attr: u32) -> [u8; 8] {
+    (INDEX_FLAG | (u64::from(index) << 32) | u64::from(attr)).to_be_bytes()
+}
+
+/// Composite key of a secondary index entry, the primary key follows the prefix
+pub fn index_key(index: u8, attr: u32, primary: &[u8]) -> Vec<u8> {
+    let mut key = index_prefix(index, attr).to_vec();
+    key.extend_from_slice(primary);
+    key
+}
+
+/// splitmix64 finalizer, a bijection so hashed ids never collide
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::time::Duration;
+
+/// Buckets per power of two, which bounds the error of percentiles to 1/8
+const SUB_BUCKETS: u64 = 8;
+
+const BUCKETS: usize = 64 * SUB_BUCKETS as usize;
+
+/// Latencies of one kind of operation (in µs)
+///
+/// The sum is taken by the metrics thread to compute averages,
+/// the log-linear histogram lives for a whole phase to compute percentiles
+pub struct Latency {
+    sum: AtomicU64,
+    max: AtomicU64,
+    buckets: Box<[AtomicU64]>,
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self {
+            sum: AtomicU64::default(),
+            max: AtomicU64::default(),
+            buckets: (0..BUCKETS).map(|_| AtomicU64::default()).collect(),
+        }
+    }
+}
+
+impl Latency {
+    pub fn record(&self, elapsed: Duration) {
+        self.record_many(elapsed, 1);
+    }
+
+    /// Records `count` operations which took `elapsed` together
+    pub fn record_many(&self, elapsed: Duration, count: u64) {
+        let micros = elapsed.as_micros() as u64;
+        let each = micros / count.max(1);
+
+        self.sum.fetch_add(micros, Relaxed);
+        self.max.fetch_max(each, Relaxed);
+        self.buckets[bucket_idx(each)].fetch_add(count, Relaxed);
+    }
+
+    /// Returns the sum of latencies since the last call
+    pub fn take_sum(&self) -> u64 {
+        self.sum.swap(0, Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.sum.store(0, Relaxed);
+        self.max.store(0, Relaxed);
+
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Relaxed);
+        }
+    }
+
+    /// Adds the latencies recorded by `other`
+    pub fn merge_from(&self, other: &Latency) {
+        self.sum.fetch_add(other.sum.load(Relaxed), Relaxed);
+        self.max.fetch_max(other.max.load(Relaxed), Relaxed);
+
+        for (bucket, other) in self.buckets.iter().zip(other.buckets.iter()) {
+            bucket.fetch_add(other.load(Relaxed), Relaxed);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().map(|x| x.load(Relaxed)).sum()
+    }
+
+    /// Upper bound of the latency below which `q` of the operations are
+    pub fn percentile(&self, q: f64) -> u64 {
+        let target = ((self.count() as f64 * q).ceil() as u64).max(1);
+
+        let mut seen = 0;
+
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Relaxed);
+
+            if seen >= target {
+                return bucket_upper_bound(idx).min(self.max.load(Relaxed));
+            }
+        }
+
+        0
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "count": self.count(),
+            "p50": self.percentile(0.5),
+            "p90": self.percentile(0.9),
+            "p99": self.percentile(0.99),
+            "p999": self.percentile(0.999),
+            "max": self.max.load(Relaxed),
+        })
+    }
+}
+
+fn bucket_idx(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+
+    let exp = u64::from(63 - micros.leading_zeros());
+    let sub = (micros >> (exp - 3)) & (SUB_BUCKETS - 1);
+
+    ((exp - 2) * SUB_BUCKETS + sub) as usize
+}
+
+fn bucket_upper_bound(idx: usize) -> u64 {
+    let idx = idx as u64;
+
+    if idx < SUB_BUCKETS {
+        return idx;
+    }
+
+    let exp = idx / SUB_BUCKETS + 2;
+    let sub = idx % SUB_BUCKETS;
+
+    let lower = (SUB_BUCKETS + sub) << (exp - 3);
+
+    // NOTE: Subtract first, the last bucket ends at u64::MAX
+    lower + ((1 << (exp - 3)) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_values_have_exact_buckets() {
+        for micros in 0..=8 {
+            assert_eq!(bucket_idx(micros), micros as usize);
+            assert_eq!(bucket_upper_bound(bucket_idx(micros)), micros);
+        }
+    }
+
+    #[test]
+    fn powers_of_two_start_a_bucket() {
+        for exp in 3..64 {
+            let micros = 1_u64 << exp;
+            let idx = bucket_idx(micros);
+
+            assert_eq!(idx, (exp - 2) * SUB_BUCKETS as usize);
+            assert_eq!(bucket_idx(micros - 1), idx - 1);
+            assert_eq!(bucket_upper_bound(idx - 1), micros - 1);
+        }
+    }
+
+    #[test]
+    fn upper_bound_is_within_an_eighth() {
+        for micros in [9, 100, 1_000, 123_456, u64::MAX / 3, u64::MAX] {
+            let upper = bucket_upper_bound(bucket_idx(micros));
+
+            assert!(upper >= micros);
+            assert!(upper - micros <= micros / SUB_BUCKETS);
+        }
+    }
+
+    #[test]
+    fn max_value_fits() {
+        assert!(bucket_idx(u64::MAX) < BUCKETS);
+        assert_eq!(bucket_upper_bound(bucket_idx(u64::MAX)), u64::MAX);
+    }
+
+    #[test]
+    fn empty_histogram() {
+        let latency = Latency::default();
+
+        assert_eq!(latency.count(), 0);
+        assert_eq!(latency.percentile(0.5), 0);
+        assert_eq!(latency.percentile(1.0), 0);
+        assert_eq!(latency.take_sum(), 0);
+    }
+
+    #[test]
+    fn percentiles_are_capped_at_max() {
+        let latency = Latency::default();
+
+        for micros in 1..=1_000 {
+            latency.record(Duration::from_micros(micros));
+        }
+
+        assert_eq!(latency.count(), 1_000);
+
+        let p50 = latency.percentile(0.5);
+        assert!((500..=500 + 500 / SUB_BUCKETS).contains(&p50));
+
+        assert_eq!(latency.percentile(1.0), 1_000);
+    }
+
+    #[test]
+    fn record_many_splits_elapsed_time() {
+        let latency = Latency::default();
+        latency.record_many(Duration::from_micros(400), 4);
+
+        assert_eq!(latency.count(), 4);
+        assert_eq!(latency.percentile(0.5), 100);
+        assert_eq!(latency.percentile(1.0), 100);
+        assert_eq!(latency.take_sum(), 400);
+    }
+}
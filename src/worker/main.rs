@@ -1,18 +1,33 @@
+mod counters;
 mod db;
-
+mod diskstats;
+mod key_dist;
+mod key_gen;
+mod latency;
+mod proc_cpu;
+mod proc_io;
+mod proc_mem;
+mod redb_backend;
+mod value_gen;
+mod workload;
+
+use crate::counters::Counters;
 use crate::db::DatabaseWrapper;
+use crate::diskstats::DiskStats;
+use crate::proc_cpu::CpuSampler;
+use crate::proc_io::ProcIo;
+use crate::proc_mem::ProcMem;
+use crate::workload::{spawn, WorkerContext};
 use clap::Parser;
-use db::GenericDatabase;
-use rand::distributions::Distribution;
-use rand::Rng;
+use db::{partition_name, GenericDatabase};
 use rust_storage_bench::{Args, Backend, Workload};
-use std::fs::{create_dir_all, remove_dir_all};
+use std::fs::{create_dir_all, remove_dir_all, File};
 use std::io::Write;
 use std::path::Path;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use sysinfo::Pid;
-use zipf::ZipfDistribution;
 
 /// Gets the unix timestamp as a duration
 pub fn unix_timestamp() -> std::time::Duration {
@@ -24,11 +39,63 @@ pub fn unix_timestamp() -> std::time::Duration {
         .unwrap()
 }
 
-fn start_killer(min: u64) {
-    std::thread::spawn(move || {
-        std::thread::sleep(Duration::from_secs(min * 60));
-        std::process::exit(0);
-    });
+/// What the worker threads are busy with
+///
+/// Warmup and run phases carry their step in the workload schedule,
+/// so running a workload twice still makes for distinct phases
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Load,
+
+    /// Operations run, but are excluded from the reported statistics
+    Warmup(usize, Workload),
+
+    Run(usize, Workload),
+}
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Load => "load",
+            Self::Warmup(..) => "warmup",
+            Self::Run(..) => "run",
+        }
+    }
+
+    fn step(&self) -> Option<usize> {
+        match self {
+            Self::Load => None,
+            Self::Warmup(step, _) | Self::Run(step, _) => Some(*step),
+        }
+    }
+
+    fn workload(&self) -> Option<Workload> {
+        match self {
+            Self::Load => None,
+            Self::Warmup(_, workload) | Self::Run(_, workload) => Some(*workload),
+        }
+    }
+}
+
+/// Appends a record to the JSONL log, which is shared by the metrics thread and the main thread
+fn write_record(log: &Mutex<File>, json: &serde_json::Value) {
+    let mut file = log.lock().unwrap();
+    writeln!(file, "{}", serde_json::to_string(json).unwrap()).unwrap();
+}
+
+/// Marks the start of a phase in the log, so charts can show the transitions between workloads
+fn write_phase_record(log: &Mutex<File>, phase: Phase, duration: Option<Duration>) {
+    write_record(
+        log,
+        &serde_json::json!({
+            "time_micro": unix_timestamp().as_micros(),
+            "type": "phase",
+            "phase": phase.name(),
+            "step": phase.step(),
+            "workload": phase.workload(),
+            "secs": duration.map(|x| x.as_secs()),
+        }),
+    );
 }
 
 /*
@@ -50,10 +117,27 @@ fn main() {
     eprintln!("Threads : {}", args.threads);
     eprintln!("# items : {}", args.items);
 
-    if args.workload != Workload::TaskC {
-        if args.fsync && (args.backend == Backend::Sled/*|| args.backend == Backend::Bloodstone*/) {
-            panic!("Sled doesn't fsync...");
-        }
+    assert!(
+        args.phase_minutes.len() <= args.workload.len(),
+        "--phase-minutes has more entries than --workload"
+    );
+
+    assert!(
+        args.ops.is_none() || args.warmup_secs == 0,
+        "--ops can't be combined with --warmup-secs, as warmup operations would count"
+    );
+
+    let has_writers = if args.uses_thread_pools() {
+        args.writer_threads.unwrap_or(0) > 0
+    } else {
+        args.workload.iter().any(|x| *x != Workload::TaskC)
+    };
+
+    if has_writers
+        && args.fsync
+        && (args.backend == Backend::Sled/*|| args.backend == Backend::Bloodstone*/)
+    {
+        panic!("Sled doesn't fsync...");
     }
 
     let data_dir = Path::new(".data").join(match args.backend {
@@ -68,6 +152,12 @@ fn main() {
         remove_dir_all(&data_dir).unwrap();
     }
 
+    let partition_names = (0..args.partitions.into())
+        .map(partition_name)
+        .collect::<Vec<_>>();
+
+    let mut redb_io = None;
+
     let db = match args.backend {
         #[cfg(feature = "rocksdb")]
         Backend::RocksDb => {
@@ -76,9 +166,49 @@ fn main() {
             let mut opts = rocksdb::Options::default();
             opts.set_manual_wal_flush(true);
             opts.create_if_missing(true);
+            opts.create_missing_column_families(true);
+
+            // NOTE: Read-modify-writes are done using merges
+            let mut cf_opts = rocksdb::Options::default();
+            cf_opts.set_merge_operator_associative("counter", db::rocksdb_counter_merge);
 
-            let db = rocksdb::DB::open(&opts, &data_dir).unwrap();
-            GenericDatabase::RocksDb(Arc::new(db))
+            if args.rocksdb_blob_files {
+                cf_opts.set_enable_blob_files(true);
+                cf_opts.set_min_blob_size(args.rocksdb_min_blob_size.into());
+                cf_opts.set_enable_blob_gc(true);
+            }
+
+            // NOTE: The first partition is the default column family
+            let column_families = std::iter::once("default".to_owned())
+                .chain(partition_names.iter().skip(1).cloned())
+                .collect::<Vec<_>>();
+
+            let db = rocksdb::DB::open_cf_with_opts(
+                &opts,
+                &data_dir,
+                column_families.iter().map(|name| (name, cf_opts.clone())),
+            )
+            .unwrap();
+
+            GenericDatabase::RocksDb {
+                db: Arc::new(db),
+                column_families,
+            }
+        }
+
+        #[cfg(feature = "surrealkv")]
+        Backend::SurrealKv => {
+            // NOTE: surrealkv has no blocks, so --lsm-block-size does not apply;
+            // the cache only holds values (keys are always kept in memory)
+            if args.partitions > 1 {
+                log::warn!("WARNING: surrealkv has no partitions, using a single keyspace");
+            }
+
+            let mut opts = surrealkv::Options::new();
+            opts.dir = data_dir.clone();
+            opts.max_value_cache_size = args.cache_size.into();
+
+            GenericDatabase::SurrealKv(Arc::new(surrealkv::Store::new(opts).unwrap()))
         }
 
         #[cfg(feature = "heed")]
@@ -88,15 +218,26 @@ fn main() {
             let env = unsafe {
                 heed::EnvOpenOptions::new()
                     .map_size(8_000_000_000)
+                    .max_dbs(args.partitions.into())
                     .open(&data_dir)
                     .unwrap()
             };
 
             let mut wtxn = env.write_txn().unwrap();
-            let db = env.create_database(&mut wtxn, None).unwrap();
+
+            // NOTE: A single partition uses the unnamed database
+            let dbs = if args.partitions == 1 {
+                vec![env.create_database(&mut wtxn, None).unwrap()]
+            } else {
+                partition_names
+                    .iter()
+                    .map(|name| env.create_database(&mut wtxn, Some(name)).unwrap())
+                    .collect()
+            };
+
             wtxn.commit().unwrap();
 
-            GenericDatabase::Heed { db, env }
+            GenericDatabase::Heed { dbs, env }
         }
         Backend::Fjall => {
             use fjall::{
@@ -113,23 +254,45 @@ fn main() {
                 .fsync_ms(if args.fsync { None } else { Some(1_000) })
                 .block_cache(BlockCache::with_capacity_bytes(args.cache_size.into()).into());
 
-            let create_opts =
-                PartitionCreateOptions::default().block_size(args.lsm_block_size.into());
-
             let keyspace = config.open().unwrap();
-            let db = keyspace.open_partition("data", create_opts).unwrap();
-            db.set_compaction_strategy(compaction_strategy);
 
-            GenericDatabase::Fjall { keyspace, db }
+            let partitions = partition_names
+                .iter()
+                .map(|name| {
+                    let create_opts =
+                        PartitionCreateOptions::default().block_size(args.lsm_block_size.into());
+
+                    let db = keyspace.open_partition(name, create_opts).unwrap();
+                    db.set_compaction_strategy(compaction_strategy.clone());
+                    db
+                })
+                .collect();
+
+            GenericDatabase::Fjall {
+                keyspace,
+                partitions,
+            }
         }
-        Backend::Sled => GenericDatabase::Sled(
-            sled::Config::new()
+        Backend::Sled => {
+            let db = sled::Config::new()
                 .path(&data_dir)
                 .flush_every_ms(if args.fsync { None } else { Some(1_000) })
                 .cache_capacity(args.cache_size as u64)
                 .open()
-                .unwrap(),
-        ),
+                .unwrap();
+
+            // NOTE: The first partition is the default tree
+            let trees = std::iter::once((*db).clone())
+                .chain(
+                    partition_names
+                        .iter()
+                        .skip(1)
+                        .map(|name| db.open_tree(name).unwrap()),
+                )
+                .collect();
+
+            GenericDatabase::Sled { db, trees }
+        }
         // Backend::Bloodstone => GenericDatabase::Bloodstone(
         //     bloodstone::Config::new()
         //         .cache_capacity_bytes(args.cache_size as usize)
@@ -142,14 +305,20 @@ fn main() {
 
             let db = jammdb::DB::open(data_dir.join("data.db")).unwrap();
             let tx = db.tx(true).unwrap();
-            let _ = tx.create_bucket("data").unwrap();
+            for name in &partition_names {
+                let _ = tx.create_bucket(name.as_str()).unwrap();
+            }
             tx.commit().unwrap();
 
-            GenericDatabase::Jamm(db)
+            GenericDatabase::Jamm {
+                db,
+                buckets: partition_names.clone(),
+                tx_start: Arc::default(),
+            }
         }
 
         Backend::Persy => {
-            use persy::{Config, Persy, PersyId, ValueMode};
+            use persy::{ByteVec, Config, Persy, PersyId, ValueMode};
 
             create_dir_all(&data_dir).unwrap();
 
@@ -159,24 +328,69 @@ fn main() {
             cfg.change_cache_size(args.cache_size.into());
             let db = Persy::open(data_dir.join("data.persy"), cfg).unwrap();
 
+            let partitions = partition_names
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| {
+                    let index = if idx == 0 {
+                        "primary".to_owned()
+                    } else {
+                        format!("primary{idx}")
+                    };
+                    (name.clone(), index)
+                })
+                .collect::<Vec<_>>();
+
             let mut tx = db.begin().unwrap();
-            tx.create_segment("data").unwrap();
-            tx.create_index::<String, PersyId>("primary", ValueMode::Replace)
-                .unwrap();
+            for (segment, index) in &partitions {
+                tx.create_segment(segment).unwrap();
+                tx.create_index::<ByteVec, PersyId>(index, ValueMode::Replace)
+                    .unwrap();
+            }
             let prepared = tx.prepare().unwrap();
             prepared.commit().unwrap();
 
-            GenericDatabase::Persy(db)
+            GenericDatabase::Persy { db, partitions }
         }
         Backend::Redb => {
             create_dir_all(&data_dir).unwrap();
 
-            GenericDatabase::Redb(Arc::new(
-                redb::Builder::new()
-                    .set_cache_size(args.cache_size as usize)
-                    .create(data_dir.join("my_db.redb"))
-                    .unwrap(),
-            ))
+            let mut builder = redb::Builder::new();
+            builder.set_cache_size(args.cache_size as usize);
+
+            let path = data_dir.join("my_db.redb");
+
+            let db = if args.redb_instrument_io
+                || args.redb_io_latency_us > 0
+                || args.redb_sync_latency_us > 0
+                || args.redb_fail_after_writes.is_some()
+            {
+                let (backend, counters) = redb_backend::InstrumentedBackend::open(
+                    path,
+                    Duration::from_micros(args.redb_io_latency_us.into()),
+                    Duration::from_micros(args.redb_sync_latency_us.into()),
+                    args.redb_fail_after_writes,
+                )
+                .unwrap();
+
+                redb_io = Some(counters);
+
+                builder.create_with_backend(backend).unwrap()
+            } else {
+                builder.create(path).unwrap()
+            };
+
+            // NOTE: Create all tables up front, so reads never hit a missing table
+            let tx = db.begin_write().unwrap();
+            for name in &partition_names {
+                tx.open_table(db::redb_table(name)).unwrap();
+            }
+            tx.commit().unwrap();
+
+            GenericDatabase::Redb {
+                db: Arc::new(db),
+                tables: partition_names.clone(),
+            }
         }
         Backend::Nebari => {
             use nebari::{
@@ -189,41 +403,55 @@ fn main() {
             let roots = Config::default_for(data_dir.join("db.nebari"))
                 .open()
                 .unwrap();
-            let tree = roots.tree(Unversioned::tree("data")).unwrap();
+            let trees = partition_names
+                .iter()
+                .map(|name| roots.tree(Unversioned::tree(name.clone())).unwrap())
+                .collect();
 
-            GenericDatabase::Nebari { roots, tree }
+            GenericDatabase::Nebari { roots, trees }
         }
     };
 
+    // NOTE: One slot per worker (or load) thread, plus one shared by all other threads
+    let counter_slots = args.worker_threads().max(args.load_threads.into()) + 1;
+
     let db = DatabaseWrapper {
         inner: db,
-        write_ops: Default::default(),
-        read_ops: Default::default(),
-        delete_ops: Default::default(),
-        scan_ops: Default::default(),
-        read_latency: Default::default(),
-        write_latency: Default::default(),
+        counters: Arc::new((0..counter_slots).map(|_| Counters::default()).collect()),
+        slot: counter_slots - 1,
+        key_locks: Default::default(),
     };
 
-    {
-        let db = db.clone();
-        let args = args.clone();
+    let ctx = WorkerContext::new(args.clone(), db.clone());
 
-        std::thread::spawn(move || {
-            use std::sync::atomic::Ordering::Relaxed;
+    let phase = Arc::new(Mutex::new(Phase::Load));
+    let log = Arc::new(Mutex::new(File::create(&args.out).unwrap()));
 
-            let backend = match args.backend {
-                Backend::Fjall => format!("{} {}", args.backend, args.lsm_compaction),
-                _ => args.backend.to_string(),
-            };
+    let backend = match args.backend {
+        Backend::Fjall => format!("{} {}", args.backend, args.lsm_compaction),
+        _ => args.backend.to_string(),
+    };
+
+    // NOTE: Dropping the sender makes the metrics thread write a last record and return
+    let (shutdown_tx, shutdown_rx) = std::sync::mpsc::channel::<()>();
+
+    let metrics_thread = {
+        let db = db.clone();
+        let args = args.clone();
+        let phase = phase.clone();
+        let log = log.clone();
+        let thread_ops = ctx.thread_ops.clone();
+        let backend = backend.clone();
 
+        spawn("bench-metrics".into(), move || {
             let mut sys = sysinfo::System::new_all();
             sys.refresh_all();
 
             let pid = std::process::id();
             let pid = Pid::from(pid as usize);
 
-            let mut file_writer = std::fs::File::create(&args.out).unwrap();
+            let data_device = DiskStats::device_of(&data_dir);
+            let first_disk_stats = data_device.and_then(DiskStats::read);
 
             {
                 let json = serde_json::json!({
@@ -235,12 +463,7 @@ fn main() {
                     "mem": sys.total_memory(),
                 });
 
-                writeln!(
-                    &mut file_writer,
-                    "{}",
-                    serde_json::to_string(&json).unwrap()
-                )
-                .unwrap();
+                write_record(&log, &json);
             }
 
             {
@@ -250,26 +473,73 @@ fn main() {
                     "backend": backend.to_string(),
                     "workload": args.workload,
                     "threads": args.threads,
+                    "reader_threads": args.reader_threads,
+                    "writer_threads": args.writer_threads,
                     "items": args.items,
+                    "records": workload::shared_records(&args),
+                    "key_size": args.key_size,
+                    "key_size_max": args.key_size_max,
+                    "key_order": args.key_order,
+                    "partitions": args.partitions,
+                    "key_distribution": args
+                        .workload
+                        .iter()
+                        .map(|x| args.key_distribution.unwrap_or(x.default_key_distribution()))
+                        .collect::<Vec<_>>(),
+                    "zipf_theta": args.zipf_theta,
+                    "hotspot_fraction": args.hotspot_fraction,
+                    "hotspot_ops_fraction": args.hotspot_ops_fraction,
                     "value_size": args.value_size,
-                    "cache_size_in_bytes": args.cache_size
+                    "value_size_distribution": args.value_size_distribution,
+                    "load_threads": args.load_threads,
+                    "load_batch_size": args.load_batch_size,
+                    "phase_secs": (0..args.workload.len())
+                        .map(|step| args.phase_duration(step).as_secs())
+                        .collect::<Vec<_>>(),
+                    "ops": args.ops,
+                    "ops_per_thread": args.ops_per_thread,
+                    "thread_metrics": args.thread_metrics,
+                    "warmup_secs": args.warmup_secs,
+                    "read_batch_size": args.read_batch_size,
+                    "retention_secs": args.retention_secs,
+                    "scan_window_ms": args.scan_window_ms,
+                    "blob_size_min": args.blob_size_min,
+                    "blob_size_max": args.blob_size_max,
+                    "rocksdb_blob_files": args.rocksdb_blob_files,
+                    "rmw_isolation": db.rmw_isolation(),
+                    "secondary_indexes": args.secondary_indexes,
+                    "index_cardinality": args.index_cardinality,
+                    "compressibility": args.compressibility,
+                    "cache_size_in_bytes": args.cache_size,
+                    "data_device": first_disk_stats.as_ref().map(|x| &x.device),
                 });
 
-                writeln!(
-                    &mut file_writer,
-                    "{}",
-                    serde_json::to_string(&json).unwrap()
-                )
-                .unwrap();
+                write_record(&log, &json);
             }
 
-            let mut prev_write_ops = 0;
-            let mut prev_read_ops = 0;
+            let mut prev_ops = vec![[0; 8]; db.counters.len()];
+            let mut prev_phase = Phase::Load;
+            let mut prev_thread_ops = vec![0; thread_ops.len()];
+            let mut prev_tick = Instant::now();
+            let mut prev_io = ProcIo::read();
+            let mut cpu_sampler = CpuSampler::start();
+            let mut prev_mem = ProcMem::read();
+            let mut prev_disk_stats = first_disk_stats.clone();
+            let mut shutting_down = false;
 
             loop {
                 if let Ok(du_bytes) = fs_extra::dir::get_size(&data_dir) {
                     sys.refresh_all();
 
+                    let phase = *phase.lock().unwrap();
+
+                    // NOTE: Op counters are reset when a new phase starts
+                    if phase != prev_phase {
+                        prev_ops.fill([0; 8]);
+                        prev_thread_ops.fill(0);
+                        prev_phase = phase;
+                    }
+
                     let cpu = sys.global_cpu_info().cpu_usage();
 
                     let proc = sys.processes();
@@ -278,38 +548,24 @@ fn main() {
                     let mem = child.memory() as f32;
                     let disk = child.disk_usage();
 
-                    let write_ops = db.write_ops.load(Relaxed);
-                    let read_ops = db.read_ops.load(Relaxed);
-
-                    let dataset_size_bytes =
-                        write_ops as f64 * (args.key_size as f64 + args.value_size as f64);
+                    let dataset_size_bytes = db.total(|x| &x.write_bytes) as f64;
 
                     let space_amp = du_bytes as f64 / dataset_size_bytes;
 
                     let write_amp = disk.total_written_bytes as f64 / dataset_size_bytes;
 
-                    let accumulated_write_latency = db
-                        .write_latency
-                        .fetch_min(0, std::sync::atomic::Ordering::Release);
-
-                    let accumulated_read_latency = db
-                        .read_latency
-                        .fetch_min(0, std::sync::atomic::Ordering::Release);
-
-                    let write_ops_since = write_ops - prev_write_ops;
-                    let read_ops_since = read_ops - prev_read_ops;
-
-                    let avg_write_latency = accumulated_write_latency / write_ops_since.max(1);
-                    let avg_read_latency = accumulated_read_latency / read_ops_since.max(1);
+                    let record_type = match phase {
+                        Phase::Load => "load",
+                        Phase::Warmup(..) | Phase::Run(..) => "metrics",
+                    };
 
-                    let json = serde_json::json!({
+                    let mut json = serde_json::json!({
                         "backend": backend,
-                        "type": "metrics",
+                        "type": record_type,
+                        "phase": phase.name(),
+                        "step": phase.step(),
+                        "workload": phase.workload(),
                         "time_micro": unix_timestamp().as_micros(),
-                        "write_ops": write_ops,
-                        "read_ops": read_ops,
-                        "delete_ops": db.delete_ops,
-                        "scan_ops": db.scan_ops,
                         "cpu": cpu,
                         "mem_bytes": mem,
                         "mem_mib": mem / 1024.0 / 1024.0,
@@ -322,449 +578,304 @@ fn main() {
                         "space_amp": space_amp,
                         "write_amp": write_amp,
                         "dataset_size": dataset_size_bytes,
-                        "avg_write_latency": avg_write_latency,
-                        "avg_read_latency": avg_read_latency,
+                        "rmw_conflicts": db.total(|x| &x.rmw_conflicts),
+                        "tx_aborts": db.total(|x| &x.tx_aborts),
+                        "empty_pops": db.total(|x| &x.empty_pops),
+                        "pop_conflicts": db.total(|x| &x.pop_conflicts),
+                        "failed_writes": db.total(|x| &x.failed_writes),
                     });
 
-                    prev_write_ops = write_ops;
-                    prev_read_ops = read_ops;
-
-                    writeln!(
-                        &mut file_writer,
-                        "{}",
-                        serde_json::to_string(&json).unwrap()
-                    )
-                    .unwrap();
-                }
-
-                // As minutes increase, decrease granularity
-                // to keep log files low(ish)
-                let sec = args.minutes as f32 / 2.0;
-                let duration = Duration::from_secs_f32(sec);
-                std::thread::sleep(duration);
-            }
-        });
-    }
-
-    match args.workload {
-        Workload::TaskA => {
-            let users = args.threads;
-
-            {
-                let mut rng = rand::thread_rng();
-
-                for idx in 0..users {
-                    let user_id = format!("user{idx:0>2}");
-
-                    for x in 0..args.items {
-                        let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                        for _ in 0..args.value_size {
-                            val.push(rng.gen::<u8>());
-                        }
-
-                        let key = format!("{user_id}:{x:0>10}");
-                        let key = key.as_bytes();
-
-                        db.insert(key, &val, false, args.clone());
-                    }
-                }
-            }
-
-            let threads = (0..users)
-                .map(|idx| {
-                    let args = args.clone();
-                    let db = db.clone();
-                    let user_id = format!("user{idx:0>2}");
-
-                    std::thread::spawn(move || {
-                        let mut rng = rand::thread_rng();
-
-                        let zipf = ZipfDistribution::new((args.items - 1) as usize, 0.99).unwrap();
-
-                        loop {
-                            let x = zipf.sample(&mut rng);
-                            let key = format!("{user_id}:{x:0>10}");
-                            let key = key.as_bytes();
-
-                            let choice: f32 = rng.gen_range(0.0..1.0);
-
-                            if choice > 0.5 {
-                                let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                                for _ in 0..args.value_size {
-                                    val.push(rng.gen::<u8>());
-                                }
-
-                                db.insert(key, &val, args.fsync, args.clone());
-                            } else {
-                                db.get(key).unwrap();
+                    // NOTE: Totals and averages over all threads, per kind of operation
+                    let mut totals = [("", 0, 0, 0); 8];
+                    let mut threads = vec![];
+
+                    for (counters, prev) in db.counters.iter().zip(prev_ops.iter_mut()) {
+                        let mut thread = serde_json::Map::new();
+
+                        for (idx, (name, ops, latency)) in counters.ops().into_iter().enumerate() {
+                            let ops = ops.load(Relaxed);
+                            let since = ops.saturating_sub(prev[idx]);
+                            let latency = latency.take_sum();
+                            prev[idx] = ops;
+
+                            let total = &mut totals[idx];
+                            total.0 = name;
+                            total.1 += ops;
+                            total.2 += since;
+                            total.3 += latency;
+
+                            if since > 0 {
+                                thread.insert(format!("{name}_ops"), since.into());
+                                thread.insert(
+                                    format!("avg_{name}_latency"),
+                                    (latency / since).into(),
+                                );
                             }
                         }
-                    })
-                })
-                .collect::<Vec<_>>();
-
-            start_killer(args.minutes.into());
-
-            for t in threads {
-                t.join().unwrap();
-            }
-        }
-
-        Workload::TaskB => {
-            let users = args.threads;
-
-            {
-                let mut rng = rand::thread_rng();
-
-                for idx in 0..users {
-                    let user_id = format!("user{idx:0>2}");
-
-                    for x in 0..args.items {
-                        let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                        for _ in 0..args.value_size {
-                            val.push(rng.gen::<u8>());
-                        }
-
-                        let key = format!("{user_id}:{x:0>10}");
-                        let key = key.as_bytes();
 
-                        db.insert(key, &val, false, args.clone());
+                        threads.push(thread);
                     }
-                }
-            }
 
-            let threads = (0..users)
-                .map(|idx| {
-                    let args = args.clone();
-                    let db = db.clone();
-                    let user_id = format!("user{idx:0>2}");
-
-                    std::thread::spawn(move || {
-                        let mut rng = rand::thread_rng();
+                    for (name, ops, since, latency) in totals {
+                        json[format!("{name}_ops")] = ops.into();
+                        json[format!("avg_{name}_latency")] = (latency / since.max(1)).into();
+                    }
 
-                        let zipf = ZipfDistribution::new((args.items - 1) as usize, 0.99).unwrap();
+                    if args.thread_metrics {
+                        json["threads"] = threads.into();
+                    }
 
-                        loop {
-                            let x = zipf.sample(&mut rng);
-                            let key = format!("{user_id}:{x:0>10}");
-                            let key = key.as_bytes();
+                    let elapsed = prev_tick.elapsed().as_secs_f64();
+                    prev_tick = Instant::now();
+
+                    if phase != Phase::Load {
+                        let thread_ops_per_sec = thread_ops
+                            .iter()
+                            .zip(prev_thread_ops.iter_mut())
+                            .map(|(ops, prev)| {
+                                let ops = ops.load(Relaxed);
+                                let since = ops.saturating_sub(*prev);
+                                *prev = ops;
+                                since as f64 / elapsed
+                            })
+                            .collect::<Vec<_>>();
+
+                        json["ops_per_sec"] = thread_ops_per_sec.iter().sum::<f64>().into();
+                        json["fairness"] = fairness(&thread_ops_per_sec).into();
+                        json["thread_ops_per_sec"] = thread_ops_per_sec.into();
+                    }
 
-                            let choice: f32 = rng.gen_range(0.0..1.0);
+                    let value_write_bytes = db
+                        .counters
+                        .iter()
+                        .map(|x| x.value_write_bytes.swap(0, Relaxed))
+                        .sum::<u64>();
+                    let value_read_bytes = db
+                        .counters
+                        .iter()
+                        .map(|x| x.value_read_bytes.swap(0, Relaxed))
+                        .sum::<u64>();
+
+                    json["value_write_mib_s"] =
+                        (value_write_bytes as f64 / 1_024.0 / 1_024.0 / elapsed).into();
+                    json["value_read_mib_s"] =
+                        (value_read_bytes as f64 / 1_024.0 / 1_024.0 / elapsed).into();
+
+                    if let (Some(io), Some(prev)) = (ProcIo::read(), &prev_io) {
+                        json["proc_io"] = io.delta_json(prev);
+                        prev_io = Some(io);
+                    }
 
-                            if choice > 0.95 {
-                                let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                                for _ in 0..args.value_size {
-                                    val.push(rng.gen::<u8>());
-                                }
+                    if let Some(cpu) = cpu_sampler.sample() {
+                        json["proc_cpu"] = cpu;
+                    }
 
-                                db.insert(key, &val, args.fsync, args.clone());
-                            } else {
-                                db.get(key).unwrap();
-                            }
-                        }
-                    })
-                })
-                .collect::<Vec<_>>();
+                    if let (Some(mem), Some(prev)) = (ProcMem::read(), &prev_mem) {
+                        json["proc_mem"] = mem.delta_json(prev);
+                        prev_mem = Some(mem);
+                    }
 
-            start_killer(args.minutes.into());
+                    if let (Some(stats), Some(prev), Some(first)) = (
+                        data_device.and_then(DiskStats::read),
+                        &prev_disk_stats,
+                        &first_disk_stats,
+                    ) {
+                        json["device_io"] = stats.delta_json(prev);
 
-            for t in threads {
-                t.join().unwrap();
-            }
-        }
+                        // NOTE: Includes kernel writeback, so mmap based engines are not undercounted
+                        json["device_write_amp"] =
+                            (stats.write_bytes.saturating_sub(first.write_bytes) as f64
+                                / dataset_size_bytes)
+                                .into();
 
-        Workload::TaskC => {
-            let mut rng = rand::thread_rng();
+                        prev_disk_stats = Some(stats);
+                    }
 
-            for x in 0..args.items {
-                let key = (x as u64).to_be_bytes();
+                    if let Some(counters) = &redb_io {
+                        json["redb_io"] = counters.to_json();
+                    }
 
-                let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                for _ in 0..args.value_size {
-                    val.push(rng.gen::<u8>());
+                    write_record(&log, &json);
                 }
 
-                db.insert(&key, &val, false, args.clone());
-            }
-
-            start_killer(args.minutes.into());
-
-            let zipf = ZipfDistribution::new((args.items - 1) as usize, 0.99).unwrap();
+                if shutting_down {
+                    break;
+                }
 
-            loop {
-                let x = zipf.sample(&mut rng);
-                let key = (x as u64).to_be_bytes();
+                // As minutes increase, decrease granularity
+                // to keep log files low(ish)
+                let sec = args
+                    .seconds
+                    .map_or(f32::from(args.minutes) * 60.0, |x| x as f32)
+                    / 120.0;
+                let duration = Duration::from_secs_f32(sec);
 
-                db.get(&key).unwrap();
+                shutting_down = !matches!(
+                    shutdown_rx.recv_timeout(duration),
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout)
+                );
             }
-        }
-
-        Workload::TaskD => {
-            let users = args.threads;
-
-            {
-                let mut rng = rand::thread_rng();
-
-                for idx in 0..users {
-                    let user_id = format!("user{idx:0>2}");
-
-                    for x in 0..args.items {
-                        let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                        for _ in 0..args.value_size {
-                            val.push(rng.gen::<u8>());
-                        }
-
-                        let key = format!("{user_id}:{x:0>10}");
-                        let key = key.as_bytes();
+        })
+    };
 
-                        db.insert(key, &val, false, args.clone());
-                    }
-                }
-            }
+    eprintln!("Loading {} items per thread", args.items);
+    write_phase_record(&log, Phase::Load, None);
+    workload::load(&ctx);
 
-            let threads = (0..users)
-                .map(|idx| {
-                    let args = args.clone();
-                    let db = db.clone();
-                    let user_id = format!("user{idx:0>2}");
+    let mut summaries = vec![];
 
-                    std::thread::spawn(move || {
-                        let mut rng = rand::thread_rng();
-                        let mut records = args.items;
+    for (step, &workload) in args.workload.iter().enumerate() {
+        // NOTE: With --ops, workloads run until their operations are done
+        let duration = args.ops.is_none().then(|| args.phase_duration(step));
 
-                        loop {
-                            let choice: f32 = rng.gen_range(0.0..1.0);
+        match (duration, args.ops) {
+            (Some(duration), _) => eprintln!("Running {workload:?} for {duration:?}"),
+            (None, Some(ops)) => eprintln!("Running {workload:?} for {ops} ops"),
+            (None, None) => unreachable!(),
+        }
 
-                            if choice > 0.95 {
-                                let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                                for _ in 0..args.value_size {
-                                    val.push(rng.gen::<u8>());
-                                }
+        let balance = (workload == Workload::TaskI).then(|| workload::total_balance(&ctx));
 
-                                let key = format!("{user_id}:{records:0>10}");
-                                let key = key.as_bytes();
+        // NOTE: Reset the op counters before switching phases, so the metrics thread
+        // never attributes load operations to a workload
+        db.reset_counters();
+        ctx.reset_thread_ops();
 
-                                db.insert(key, &val, args.fsync, args.clone());
-                                records += 1;
-                            } else {
-                                let key = format!("{user_id}:{:0>10}", records - 1);
-                                let key = key.as_bytes();
+        let first_phase = if args.warmup_secs > 0 {
+            Phase::Warmup(step, workload)
+        } else {
+            Phase::Run(step, workload)
+        };
+        *phase.lock().unwrap() = first_phase;
+        write_phase_record(&log, first_phase, duration);
 
-                                db.get(key).unwrap();
-                            }
-                        }
-                    })
-                })
-                .collect::<Vec<_>>();
+        ctx.stop.store(false, Relaxed);
+        let mut threads = workload::start(workload, &ctx);
 
-            start_killer(args.minutes.into());
+        if args.warmup_secs > 0 {
+            std::thread::sleep(Duration::from_secs(args.warmup_secs.into()));
 
-            for t in threads {
-                t.join().unwrap();
-            }
+            db.reset_counters();
+            ctx.reset_thread_ops();
+            *phase.lock().unwrap() = Phase::Run(step, workload);
+            write_phase_record(&log, Phase::Run(step, workload), duration);
         }
 
-        Workload::TaskE => {
-            let users = args.threads;
-
-            {
-                let mut rng = rand::thread_rng();
-
-                for idx in 0..users {
-                    let user_id = format!("user{idx:0>2}");
-
-                    for x in 0..args.items {
-                        let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                        for _ in 0..args.value_size {
-                            val.push(rng.gen::<u8>());
-                        }
+        let start = Instant::now();
 
-                        let key = format!("{user_id}:{x:0>10}");
-                        let key = key.as_bytes();
-
-                        db.insert(key, &val, false, args.clone());
-                    }
-                }
+        while !threads.is_empty()
+            && duration.map_or(!workload::ops_done(&ctx), |x| start.elapsed() < x)
+        {
+            // NOTE: Threads only return early if they panicked or did their share of --ops,
+            // a panicked thread never does its share, so join right away to propagate the panic
+            if let Some(idx) = threads.iter().position(|t| t.is_finished()) {
+                threads.swap_remove(idx).join().unwrap();
+                continue;
             }
 
-            let threads = (0..users)
-                .map(|idx| {
-                    let args = args.clone();
-                    let db = db.clone();
-                    let user_id = format!("user{idx:0>2}");
-
-                    std::thread::spawn(move || {
-                        let mut rng = rand::thread_rng();
-                        let mut records = args.items;
-
-                        loop {
-                            let choice: f32 = rng.gen_range(0.0..1.0);
-
-                            if choice < 0.95 {
-                                let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                                for _ in 0..args.value_size {
-                                    val.push(rng.gen::<u8>());
-                                }
-
-                                let key = format!("{user_id}:{records:0>10}");
-                                let key = key.as_bytes();
-
-                                db.insert(key, &val, args.fsync, args.clone());
-                                records += 1;
-                            } else {
-                                let key = format!("{user_id}:{:0>10}", records - 1);
-                                let key = key.as_bytes();
-
-                                db.get(key).unwrap();
-                            }
-                        }
-                    })
-                })
-                .collect::<Vec<_>>();
-
-            start_killer(args.minutes.into());
-
-            for t in threads {
-                t.join().unwrap();
-            }
+            std::thread::sleep(Duration::from_millis(10));
         }
 
-        Workload::TaskF => {
-            let users = args.threads;
+        ctx.stop.store(true, Relaxed);
 
-            {
-                let mut rng = rand::thread_rng();
+        for t in threads {
+            t.join().unwrap();
+        }
 
-                for idx in 0..users {
-                    let user_id = format!("user{idx:0>2}");
+        let mut summary = phase_summary(&ctx, step, workload, start.elapsed());
 
-                    for x in 0..args.items {
-                        let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                        for _ in 0..args.value_size {
-                            val.push(rng.gen::<u8>());
-                        }
+        if let Some(expected) = balance {
+            let actual = workload::total_balance(&ctx);
 
-                        let key = format!("{user_id:0>2}:{x:0>10}");
-                        let key = key.as_bytes();
-
-                        db.insert(key, &val, false, args.clone());
-                    }
-                }
+            if actual == expected {
+                eprintln!("Total balance unchanged");
+            } else {
+                log::error!("Total balance changed from {expected} to {actual}");
             }
 
-            let threads = (0..users)
-                .map(|idx| {
-                    let args = args.clone();
-                    let db = db.clone();
-                    let user_id = format!("user{idx:0>2}");
-
-                    std::thread::spawn(move || {
-                        let mut rng = rand::thread_rng();
-                        let mut records = args.items;
-
-                        loop {
-                            let choice: f32 = rng.gen_range(0.0..1.0);
-
-                            if choice > 0.95 {
-                                let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                                for _ in 0..args.value_size {
-                                    val.push(rng.gen::<u8>());
-                                }
-
-                                let key = format!("{user_id}:{records:0>10}");
-                                let key = key.as_bytes();
-
-                                db.insert(key, &val, args.fsync, args.clone());
-                                records += 1;
-                            } else {
-                                let zipf =
-                                    ZipfDistribution::new((records - 1) as usize, 0.99).unwrap();
-                                let x = zipf.sample(&mut rng);
-
-                                let key = format!("{user_id}:{x:0>10}");
-                                let key = key.as_bytes();
-
-                                db.get(key).unwrap();
-                            }
-                        }
-                    })
-                })
-                .collect::<Vec<_>>();
-
-            start_killer(args.minutes.into());
-
-            for t in threads {
-                t.join().unwrap();
-            }
+            summary["balance_unchanged"] = (actual == expected).into();
         }
 
-        Workload::TaskG => {
-            let users = args.threads;
+        summaries.push(summary);
+    }
 
-            {
-                let mut rng = rand::thread_rng();
+    eprintln!("Shutting down");
 
-                for idx in 0..users {
-                    let user_id = format!("user{idx:0>2}");
+    drop(shutdown_tx);
+    metrics_thread.join().unwrap();
 
-                    for x in 0..args.items {
-                        let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                        for _ in 0..args.value_size {
-                            val.push(rng.gen::<u8>());
-                        }
+    // NOTE: The database closes once the last handle is gone, so drop all others first
+    drop(ctx);
 
-                        let key = format!("{user_id}:{x:0>10}");
-                        let key = key.as_bytes();
+    let close_work = db.close_work();
 
-                        db.insert(key, &val, false, args.clone());
-                    }
-                }
-            }
-
-            let threads = (0..users)
-                .map(|idx| {
-                    let args = args.clone();
-                    let db = db.clone();
-                    let user_id = format!("user{idx:0>2}");
+    let start = Instant::now();
+    db.close();
+    let close_time = start.elapsed();
 
-                    std::thread::spawn(move || {
-                        let mut rng = rand::thread_rng();
-                        let mut records = args.items;
+    eprintln!("Closed database in {close_time:?}");
 
-                        loop {
-                            let choice: f32 = rng.gen_range(0.0..1.0);
+    write_record(
+        &log,
+        &serde_json::json!({
+            "time_micro": unix_timestamp().as_micros(),
+            "type": "summary",
+            "backend": backend,
+            "workloads": summaries,
+            "close_ms": close_time.as_millis(),
+            "close_work": close_work,
+        }),
+    );
+}
 
-                            if choice < 0.95 {
-                                let mut val: Vec<u8> = Vec::with_capacity(args.value_size as usize);
-                                for _ in 0..args.value_size {
-                                    val.push(rng.gen::<u8>());
-                                }
+/// Totals, throughput and latency percentiles of a run phase
+fn phase_summary(
+    ctx: &WorkerContext,
+    step: usize,
+    workload: Workload,
+    elapsed: Duration,
+) -> serde_json::Value {
+    let db = &ctx.db;
+
+    let thread_ops = ctx
+        .thread_ops
+        .iter()
+        .map(|x| x.load(Relaxed))
+        .collect::<Vec<_>>();
+    let ops = thread_ops.iter().sum::<u64>();
+
+    let mut summary = serde_json::json!({
+        "step": step,
+        "workload": workload,
+        "secs": elapsed.as_secs_f64(),
+        "ops": ops,
+        "ops_per_sec": ops as f64 / elapsed.as_secs_f64(),
+        "thread_ops": thread_ops,
+        "fairness": fairness(&thread_ops.iter().map(|&x| x as f64).collect::<Vec<_>>()),
+        "latency_us": {},
+    });
 
-                                let key = format!("{user_id}:{records:0>10}");
-                                let key = key.as_bytes();
+    for (idx, (name, _, _)) in db.counters[0].ops().into_iter().enumerate() {
+        summary[format!("{name}_ops")] = db.total(|x| x.ops()[idx].1).into();
 
-                                db.insert(key, &val, args.fsync, args.clone());
-                                records += 1;
-                            } else {
-                                let zipf =
-                                    ZipfDistribution::new((records - 1) as usize, 0.99).unwrap();
-                                let x = zipf.sample(&mut rng);
+        let latency = db.merged_latency(|x| x.ops()[idx].2);
 
-                                let key = format!("{user_id}:{x:0>10}");
-                                let key = key.as_bytes();
+        if latency.count() > 0 {
+            summary["latency_us"][name] = latency.to_json();
+        }
+    }
 
-                                db.get(key).unwrap();
-                            }
-                        }
-                    })
-                })
-                .collect::<Vec<_>>();
+    summary
+}
 
-            start_killer(args.minutes.into());
+/// Jain's fairness index of per-thread throughput, 1.0 if all threads did the same amount of work
+fn fairness(rates: &[f64]) -> f64 {
+    let sum = rates.iter().sum::<f64>();
+    let sum_of_squares = rates.iter().map(|x| x * x).sum::<f64>();
 
-            for t in threads {
-                t.join().unwrap();
-            }
-        }
+    if sum_of_squares == 0.0 {
+        return 1.0;
     }
+
+    sum * sum / (rates.len() as f64 * sum_of_squares)
 }
@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+/// Samples CPU time of the worker process and its threads from `/proc/self/stat`
+/// and `/proc/self/task/*/stat`
+///
+/// Threads are grouped by name without their trailing number, so e.g. all compaction
+/// threads of a backend add up to one entry
+pub struct CpuSampler {
+    ticks_per_sec: f64,
+    prev_tick: Instant,
+    prev_process: (u64, u64),
+
+    /// user + system ticks of each thread, by thread id
+    prev_threads: HashMap<u64, u64>,
+}
+
+impl CpuSampler {
+    pub fn start() -> Self {
+        // SAFETY: sysconf has no preconditions
+        let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+
+        let mut sampler = Self {
+            ticks_per_sec: if ticks_per_sec > 0 {
+                ticks_per_sec as f64
+            } else {
+                100.0
+            },
+            prev_tick: Instant::now(),
+            prev_process: (0, 0),
+            prev_threads: HashMap::new(),
+        };
+
+        sampler.sample();
+        sampler
+    }
+
+    /// CPU usage (in % of one core) since the last sample, `None` where `/proc` is not available
+    pub fn sample(&mut self) -> Option<serde_json::Value> {
+        let elapsed = self.prev_tick.elapsed().as_secs_f64();
+        self.prev_tick = Instant::now();
+
+        let pct = |ticks: u64| ticks as f64 / self.ticks_per_sec / elapsed * 100.0;
+
+        let (_, user, system) = read_stat("/proc/self/stat")?;
+        let (prev_user, prev_system) = std::mem::replace(&mut self.prev_process, (user, system));
+
+        let mut threads = HashMap::new();
+        let mut groups = BTreeMap::<String, f64>::new();
+
+        for entry in std::fs::read_dir("/proc/self/task").ok()?.flatten() {
+            let Some(tid) = entry.file_name().to_str().and_then(|x| x.parse().ok()) else {
+                continue;
+            };
+
+            // NOTE: Threads may exit while we look at them
+            let Some((name, user, system)) = read_stat(entry.path().join("stat")) else {
+                continue;
+            };
+
+            let ticks = user + system;
+            let prev = self.prev_threads.get(&tid).copied().unwrap_or(0);
+            threads.insert(tid, ticks);
+
+            *groups.entry(thread_group(&name).to_owned()).or_default() +=
+                pct(ticks.saturating_sub(prev));
+        }
+
+        self.prev_threads = threads;
+
+        Some(serde_json::json!({
+            "user_pct": pct(user.saturating_sub(prev_user)),
+            "system_pct": pct(system.saturating_sub(prev_system)),
+            "threads_pct": groups,
+        }))
+    }
+}
+
+/// Name, user and system ticks of a process or thread
+fn read_stat(path: impl AsRef<std::path::Path>) -> Option<(String, u64, u64)> {
+    let stat = std::fs::read_to_string(path).ok()?;
+
+    // NOTE: The name is in parentheses and may contain spaces or parentheses itself
+    let name_start = stat.find('(')? + 1;
+    let name_end = stat.rfind(')')?;
+
+    // NOTE: Fields after the name start with the 3rd one (state), utime and stime are the 14th and 15th
+    let mut fields = stat[name_end + 1..].split_whitespace().skip(11);
+    let user = fields.next()?.parse().ok()?;
+    let system = fields.next()?.parse().ok()?;
+
+    Some((stat[name_start..name_end].to_owned(), user, system))
+}
+
+/// Thread name without its trailing number, e.g. `rocksdb:low3` becomes `rocksdb:low`
+fn thread_group(name: &str) -> &str {
+    let trimmed = name
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .trim_end_matches(['-', '_', ' ', '#']);
+
+    if trimmed.is_empty() {
+        name
+    } else {
+        trimmed
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+/// Calls of fsync and fdatasync made by the worker process
+static FSYNCS: AtomicU64 = AtomicU64::new(0);
+static FDATASYNCS: AtomicU64 = AtomicU64::new(0);
+
+// WARNING: These shadow the libc functions for everything linked into the worker,
+// including the C/C++ backends, so syncs are counted no matter which backend makes them.
+// That changes what the benchmarked code runs, so it is opt-in using the `count-syncs` feature
+#[cfg(all(feature = "count-syncs", target_os = "linux", target_env = "gnu"))]
+#[no_mangle]
+pub extern "C" fn fsync(fd: libc::c_int) -> libc::c_int {
+    FSYNCS.fetch_add(1, Relaxed);
+
+    // SAFETY: Same as calling fsync
+    unsafe { libc::syscall(libc::SYS_fsync, fd) as libc::c_int }
+}
+
+#[cfg(all(feature = "count-syncs", target_os = "linux", target_env = "gnu"))]
+#[no_mangle]
+pub extern "C" fn fdatasync(fd: libc::c_int) -> libc::c_int {
+    FDATASYNCS.fetch_add(1, Relaxed);
+
+    // SAFETY: Same as calling fdatasync
+    unsafe { libc::syscall(libc::SYS_fdatasync, fd) as libc::c_int }
+}
+
+/// I/O of the worker process, as accounted by the kernel in `/proc/self/io`
+///
+/// `rchar`/`wchar` are what was asked for through syscalls (logical I/O),
+/// `read_bytes`/`write_bytes` what actually hit the block layer (physical I/O)
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ProcIo {
+    pub rchar: u64,
+    pub wchar: u64,
+    pub syscr: u64,
+    pub syscw: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub cancelled_write_bytes: u64,
+    pub fsyncs: u64,
+    pub fdatasyncs: u64,
+}
+
+impl ProcIo {
+    /// Returns `None` where `/proc/self/io` is not available
+    pub fn read() -> Option<Self> {
+        let text = std::fs::read_to_string("/proc/self/io").ok()?;
+
+        let mut io = Self {
+            fsyncs: FSYNCS.load(Relaxed),
+            fdatasyncs: FDATASYNCS.load(Relaxed),
+            ..Default::default()
+        };
+
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let Ok(value) = value.trim().parse() else {
+                continue;
+            };
+
+            match key {
+                "rchar" => io.rchar = value,
+                "wchar" => io.wchar = value,
+                "syscr" => io.syscr = value,
+                "syscw" => io.syscw = value,
+                "read_bytes" => io.read_bytes = value,
+                "write_bytes" => io.write_bytes = value,
+                "cancelled_write_bytes" => io.cancelled_write_bytes = value,
+                _ => {}
+            }
+        }
+
+        Some(io)
+    }
+
+    /// What happened between `prev` and `self`
+    ///
+    /// Syncs are only counted with the `count-syncs` feature, and `null` otherwise
+    pub fn delta_json(&self, prev: &Self) -> serde_json::Value {
+        let counted = |x: u64| cfg!(feature = "count-syncs").then_some(x);
+
+        serde_json::json!({
+            "rchar": self.rchar.saturating_sub(prev.rchar),
+            "wchar": self.wchar.saturating_sub(prev.wchar),
+            "syscr": self.syscr.saturating_sub(prev.syscr),
+            "syscw": self.syscw.saturating_sub(prev.syscw),
+            "read_bytes": self.read_bytes.saturating_sub(prev.read_bytes),
+            "write_bytes": self.write_bytes.saturating_sub(prev.write_bytes),
+            "cancelled_write_bytes": self
+                .cancelled_write_bytes
+                .saturating_sub(prev.cancelled_write_bytes),
+            "fsyncs": counted(self.fsyncs.saturating_sub(prev.fsyncs)),
+            "fdatasyncs": counted(self.fdatasyncs.saturating_sub(prev.fdatasyncs)),
+        })
+    }
+}
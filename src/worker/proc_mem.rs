@@ -0,0 +1,84 @@
+/// Paging, scheduling and memory of the worker process
+///
+/// Faults and context switches come from `getrusage`, the RSS breakdown from `/proc/self/status`,
+/// which tells anonymous memory apart from file pages (e.g. mmap'd database files)
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ProcMem {
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    pub voluntary_ctx_switches: u64,
+    pub involuntary_ctx_switches: u64,
+
+    /// In bytes
+    pub rss_anon: u64,
+    pub rss_file: u64,
+    pub rss_shmem: u64,
+}
+
+impl ProcMem {
+    /// Returns `None` where `/proc/self/status` is not available
+    #[cfg(unix)]
+    pub fn read() -> Option<Self> {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+
+        // SAFETY: getrusage only writes to `usage`, which is initialized on success
+        let usage = unsafe {
+            if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+                return None;
+            }
+            usage.assume_init()
+        };
+
+        let mut mem = Self {
+            minor_faults: usage.ru_minflt as u64,
+            major_faults: usage.ru_majflt as u64,
+            voluntary_ctx_switches: usage.ru_nvcsw as u64,
+            involuntary_ctx_switches: usage.ru_nivcsw as u64,
+            ..Default::default()
+        };
+
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+
+        for line in status.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+
+            // NOTE: Sizes are in kB
+            let Some(Ok(value)) = value.split_whitespace().next().map(str::parse::<u64>) else {
+                continue;
+            };
+
+            match key {
+                "RssAnon" => mem.rss_anon = value * 1_024,
+                "RssFile" => mem.rss_file = value * 1_024,
+                "RssShmem" => mem.rss_shmem = value * 1_024,
+                _ => {}
+            }
+        }
+
+        Some(mem)
+    }
+
+    #[cfg(not(unix))]
+    pub fn read() -> Option<Self> {
+        None
+    }
+
+    /// Faults and context switches since `prev`, and the current RSS
+    pub fn delta_json(&self, prev: &Self) -> serde_json::Value {
+        serde_json::json!({
+            "minor_faults": self.minor_faults.saturating_sub(prev.minor_faults),
+            "major_faults": self.major_faults.saturating_sub(prev.major_faults),
+            "voluntary_ctx_switches": self
+                .voluntary_ctx_switches
+                .saturating_sub(prev.voluntary_ctx_switches),
+            "involuntary_ctx_switches": self
+                .involuntary_ctx_switches
+                .saturating_sub(prev.involuntary_ctx_switches),
+            "rss_anon_mib": self.rss_anon as f64 / 1_024.0 / 1_024.0,
+            "rss_file_mib": self.rss_file as f64 / 1_024.0 / 1_024.0,
+            "rss_shmem_mib": self.rss_shmem as f64 / 1_024.0 / 1_024.0,
+        })
+    }
+}
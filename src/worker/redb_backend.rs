@@ -0,0 +1,128 @@
+use redb::{backends::FileBackend, StorageBackend};
+use std::{
+    fs::OpenOptions,
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// I/O calls made by redb through [`InstrumentedBackend`]
+#[derive(Debug, Default)]
+pub struct IoCounters {
+    pub reads: AtomicU64,
+    pub read_bytes: AtomicU64,
+    pub writes: AtomicU64,
+    pub write_bytes: AtomicU64,
+    pub syncs: AtomicU64,
+    pub failed_writes: AtomicU64,
+}
+
+impl IoCounters {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "reads": self.reads.load(Relaxed),
+            "read_bytes": self.read_bytes.load(Relaxed),
+            "writes": self.writes.load(Relaxed),
+            "write_bytes": self.write_bytes.load(Relaxed),
+            "syncs": self.syncs.load(Relaxed),
+            "failed_writes": self.failed_writes.load(Relaxed),
+        })
+    }
+}
+
+/// Wraps redb's file backend to count I/O calls,
+/// simulate a slow disk and fail writes at a chosen point
+#[derive(Debug)]
+pub struct InstrumentedBackend {
+    inner: FileBackend,
+    counters: Arc<IoCounters>,
+    io_latency: Duration,
+    sync_latency: Duration,
+
+    /// Every write after the N-th fails, as if the disk died
+    fail_after_writes: Option<u64>,
+}
+
+impl InstrumentedBackend {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        io_latency: Duration,
+        sync_latency: Duration,
+        fail_after_writes: Option<u64>,
+    ) -> Result<(Self, Arc<IoCounters>), redb::DatabaseError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let counters = Arc::<IoCounters>::default();
+
+        let backend = Self {
+            inner: FileBackend::new(file)?,
+            counters: counters.clone(),
+            io_latency,
+            sync_latency,
+            fail_after_writes,
+        };
+
+        Ok((backend, counters))
+    }
+
+    fn delay(latency: Duration) {
+        if !latency.is_zero() {
+            std::thread::sleep(latency);
+        }
+    }
+}
+
+impl StorageBackend for InstrumentedBackend {
+    fn len(&self) -> Result<u64, io::Error> {
+        self.inner.len()
+    }
+
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, io::Error> {
+        Self::delay(self.io_latency);
+
+        self.counters.reads.fetch_add(1, Relaxed);
+        self.counters.read_bytes.fetch_add(len as u64, Relaxed);
+
+        self.inner.read(offset, len)
+    }
+
+    fn set_len(&self, len: u64) -> Result<(), io::Error> {
+        self.inner.set_len(len)
+    }
+
+    fn sync_data(&self, eventual: bool) -> Result<(), io::Error> {
+        Self::delay(self.sync_latency);
+
+        self.counters.syncs.fetch_add(1, Relaxed);
+
+        self.inner.sync_data(eventual)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), io::Error> {
+        Self::delay(self.io_latency);
+
+        let write_idx = self.counters.writes.fetch_add(1, Relaxed);
+
+        if let Some(limit) = self.fail_after_writes {
+            if write_idx >= limit {
+                self.counters.failed_writes.fetch_add(1, Relaxed);
+                return Err(io::Error::other("injected write failure"));
+            }
+        }
+
+        self.counters
+            .write_bytes
+            .fetch_add(data.len() as u64, Relaxed);
+
+        self.inner.write(offset, data)
+    }
+}
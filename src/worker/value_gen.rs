@@ -0,0 +1,176 @@
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
+use rand_distr::{LogNormal, Normal};
+use rust_storage_bench::{Args, ValueSizeDistribution};
+use std::sync::Arc;
+
+/// Values are cut from a pregenerated pool, so generating them
+/// does not need one RNG call per byte
+const MIN_POOL_SIZE: usize = 1_024 * 1_024;
+
+/// Compressible data is built from chunks of this size,
+/// each being partly random and partly repeated
+const CHUNK_SIZE: usize = 100;
+
+enum SizeDistribution {
+    Fixed(usize),
+    Uniform(usize, usize),
+    Normal(Normal<f64>),
+    LogNormal(LogNormal<f64>),
+    Histogram(Vec<usize>, WeightedIndex<u64>),
+}
+
+#[derive(Clone)]
+pub struct ValueGenerator {
+    pool: Arc<[u8]>,
+    sizes: Arc<SizeDistribution>,
+    min_size: usize,
+    max_size: usize,
+}
+
+impl ValueGenerator {
+    pub fn new(args: &Args) -> Self {
+        let mean = args.value_size as f64;
+
+        let std_dev = args.value_size_std_dev.map(f64::from).unwrap_or(mean / 4.0);
+
+        let min_size = args.value_size_min.unwrap_or(0) as usize;
+
+        let max_size =
+            args.value_size_max
+                .map(|x| x as usize)
+                .unwrap_or(match args.value_size_distribution {
+                    ValueSizeDistribution::Fixed => args.value_size as usize,
+                    _ => args.value_size as usize * 4,
+                });
+
+        let sizes = match args.value_size_distribution {
+            ValueSizeDistribution::Fixed => SizeDistribution::Fixed(args.value_size as usize),
+            ValueSizeDistribution::Uniform => SizeDistribution::Uniform(min_size, max_size),
+            ValueSizeDistribution::Normal => {
+                SizeDistribution::Normal(Normal::new(mean, std_dev).unwrap())
+            }
+            ValueSizeDistribution::LogNormal => {
+                // NOTE: Pick mu and sigma so the value sizes have the requested mean and std dev
+                let sigma2 = (1.0 + (std_dev * std_dev) / (mean * mean)).ln();
+                let mu = mean.ln() - sigma2 / 2.0;
+                SizeDistribution::LogNormal(LogNormal::new(mu, sigma2.sqrt()).unwrap())
+            }
+            ValueSizeDistribution::Histogram => {
+                let path = args
+                    .value_size_histogram
+                    .as_ref()
+                    .expect("--value-size-histogram is required for the histogram distribution");
+
+                let (sizes, weights) = read_histogram(path);
+                SizeDistribution::Histogram(sizes, WeightedIndex::new(weights).unwrap())
+            }
+        };
+
+        let max_size = match &sizes {
+            SizeDistribution::Fixed(size) => *size,
+            SizeDistribution::Histogram(sizes, _) => sizes.iter().copied().max().unwrap_or(0),
+            _ => max_size,
+        };
+
+        Self::with_sizes(sizes, min_size, max_size, args.compressibility)
+    }
+
+    /// Values of the blob workload, uniformly sized between --blob-size-min and --blob-size-max
+    pub fn blobs(args: &Args) -> Self {
+        let min_size = args.blob_size_min as usize;
+        let max_size = args.blob_size_max as usize;
+
+        assert!(
+            max_size >= min_size,
+            "--blob-size-max must not be smaller than --blob-size-min"
+        );
+
+        Self::with_sizes(
+            SizeDistribution::Uniform(min_size, max_size),
+            min_size,
+            max_size,
+            args.compressibility,
+        )
+    }
+
+    fn with_sizes(
+        sizes: SizeDistribution,
+        min_size: usize,
+        max_size: usize,
+        compressibility: f64,
+    ) -> Self {
+        Self {
+            pool: generate_pool(max_size.max(MIN_POOL_SIZE) + max_size, compressibility),
+            sizes: Arc::new(sizes),
+            min_size,
+            max_size,
+        }
+    }
+
+    fn next_size<R: Rng>(&self, rng: &mut R) -> usize {
+        let clamp =
+            |size: f64| (size.round().max(0.0) as usize).clamp(self.min_size, self.max_size);
+
+        match self.sizes.as_ref() {
+            SizeDistribution::Fixed(size) => *size,
+            SizeDistribution::Uniform(min, max) => rng.gen_range(*min..=*max),
+            SizeDistribution::Normal(dist) => clamp(dist.sample(rng)),
+            SizeDistribution::LogNormal(dist) => clamp(dist.sample(rng)),
+            SizeDistribution::Histogram(sizes, weights) => sizes[weights.sample(rng)],
+        }
+    }
+
+    pub fn next<R: Rng>(&self, rng: &mut R) -> &[u8] {
+        let size = self.next_size(rng);
+        let offset = rng.gen_range(0..=(self.pool.len() - size));
+        &self.pool[offset..offset + size]
+    }
+}
+
+/// Generates data that compresses to roughly `compressibility` of its size
+/// (1.0 being incompressible), same as db_bench's `CompressibleString`
+fn generate_pool(size: usize, compressibility: f64) -> Arc<[u8]> {
+    let mut rng = rand::thread_rng();
+
+    let compressibility = compressibility.clamp(0.0, 1.0);
+    let random_len = ((CHUNK_SIZE as f64 * compressibility) as usize).max(1);
+
+    let mut pool = vec![0; size];
+
+    for chunk in pool.chunks_mut(CHUNK_SIZE) {
+        let (head, tail) = chunk.split_at_mut(random_len.min(chunk.len()));
+        rng.fill(head);
+
+        for (idx, byte) in tail.iter_mut().enumerate() {
+            *byte = head[idx % head.len()];
+        }
+    }
+
+    pool.into()
+}
+
+/// Reads a value size histogram, one `<size> <weight>` pair per line
+fn read_histogram(path: &str) -> (Vec<usize>, Vec<u64>) {
+    let content = std::fs::read_to_string(path).unwrap();
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split(|c: char| c.is_whitespace() || c == ',');
+            let mut next_field = || {
+                fields
+                    .find(|x| !x.is_empty())
+                    .unwrap_or_else(|| panic!("invalid histogram line: {line:?}"))
+            };
+
+            let size = next_field().parse::<usize>().unwrap();
+            let weight = next_field().parse::<u64>().unwrap();
+            (size, weight)
+        })
+        .unzip()
+}
@@ -0,0 +1,852 @@
+use crate::counters::Padded;
+use crate::db::{counter, DatabaseWrapper};
+use crate::key_dist::KeyChooser;
+use crate::key_gen::{index_key, index_prefix, queue_prefix, series_prefix, KeyGenerator};
+use crate::value_gen::ValueGenerator;
+use rand::Rng;
+use rust_storage_bench::{Args, KeyOrder, Workload};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+    Arc,
+};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How often old time series points are deleted
+const RETENTION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Everything the worker threads share
+#[derive(Clone)]
+pub struct WorkerContext {
+    pub args: Arc<Args>,
+    pub db: DatabaseWrapper,
+    pub key_gen: KeyGenerator,
+    pub values: ValueGenerator,
+
+    /// Records inserted so far, per user
+    pub record_counts: Arc<Vec<AtomicU64>>,
+
+    /// Operations done by each worker thread in the current phase
+    ///
+    /// Padded like the op counters, as each thread bumps its own on every operation
+    pub thread_ops: Arc<Vec<Padded<AtomicU64>>>,
+
+    /// Set to make the worker threads return
+    pub stop: Arc<AtomicBool>,
+
+    /// Time series timestamps are microseconds since this
+    pub epoch: Instant,
+
+    /// Sequence number of the next item pushed to the queue
+    pub queue_tail: Arc<AtomicU64>,
+}
+
+impl WorkerContext {
+    pub fn new(args: Arc<Args>, db: DatabaseWrapper) -> Self {
+        let record_counts = (0..args.threads)
+            .map(|_| AtomicU64::new(args.items.into()))
+            .collect::<Vec<_>>();
+
+        let thread_ops = (0..args.worker_threads())
+            .map(|_| Padded::default())
+            .collect::<Vec<_>>();
+
+        Self {
+            key_gen: KeyGenerator::new(&args),
+            values: ValueGenerator::new(&args),
+            record_counts: Arc::new(record_counts),
+            thread_ops: Arc::new(thread_ops),
+            stop: Arc::default(),
+            epoch: Instant::now(),
+            queue_tail: Arc::default(),
+            args,
+            db,
+        }
+    }
+
+    /// Context of worker thread `idx`, which counts its operations separately
+    pub fn for_thread(&self, idx: usize) -> Self {
+        Self {
+            db: self.db.for_thread(idx),
+            ..self.clone()
+        }
+    }
+
+    pub fn reset_thread_ops(&self) {
+        for counter in self.thread_ops.iter() {
+            counter.store(0, Relaxed);
+        }
+    }
+}
+
+/// Inserts `--items` records for each user, split over `--load-threads`
+pub fn load(ctx: &WorkerContext) {
+    let users = u64::from(ctx.args.threads);
+    let items = u64::from(ctx.args.items);
+    let total = users * items;
+
+    let load_threads = u64::from(ctx.args.load_threads);
+
+    let threads = (0..load_threads)
+        .map(|idx| {
+            let ctx = ctx.for_thread(idx as usize);
+
+            let start = total * idx / load_threads;
+            let end = total * (idx + 1) / load_threads;
+
+            spawn(format!("bench-load-{idx}"), move || {
+                let WorkerContext {
+                    args,
+                    db,
+                    key_gen,
+                    values,
+                    ..
+                } = ctx;
+
+                let mut rng = rand::thread_rng();
+
+                let batch_size = args.load_batch_size as usize;
+                let mut batch = Vec::with_capacity(batch_size);
+
+                for record in start..end {
+                    let key = key_gen.key((record / items) as u8, record % items);
+                    let val = values.next(&mut rng);
+
+                    if batch_size <= 1 {
+                        db.insert(&key, val, false, args.clone());
+                        continue;
+                    }
+
+                    batch.push((key, val.to_vec()));
+
+                    if batch.len() >= batch_size {
+                        db.insert_batch(&batch, false);
+                        batch.clear();
+                    }
+                }
+
+                if !batch.is_empty() {
+                    db.insert_batch(&batch, false);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for t in threads {
+        t.join().unwrap();
+    }
+}
+
+/// Spawns the worker threads of a workload, they run until `stop` is set
+pub fn start(workload: Workload, ctx: &WorkerContext) -> Vec<JoinHandle<()>> {
+    // NOTE: The time series and queue workloads have their own take on reader and writer threads
+    if ctx.args.uses_thread_pools() && !matches!(workload, Workload::TaskJ | Workload::TaskK) {
+        return start_pools(workload, ctx);
+    }
+
+    match workload {
+        Workload::TaskA | Workload::TaskB | Workload::TaskH | Workload::TaskL => {
+            let update_ratio = if workload == Workload::TaskB {
+                0.05
+            } else {
+                0.5
+            };
+
+            let values = workload_values(workload, ctx);
+
+            (0..ctx.args.threads)
+                .map(|idx| {
+                    let ctx = ctx.for_thread(idx as usize);
+                    let values = values.clone();
+
+                    spawn(format!("bench-{idx}"), move || {
+                        let WorkerContext {
+                            args,
+                            db,
+                            key_gen,
+                            record_counts,
+                            thread_ops,
+                            stop,
+                            ..
+                        } = ctx;
+
+                        let mut rng = rand::thread_rng();
+
+                        let mut keys = KeyChooser::new(&args, workload);
+                        let records = record_counts[idx as usize].load(Relaxed);
+
+                        while running(&args, &stop, &thread_ops, idx as usize) {
+                            let x = keys.next(&mut rng, records);
+                            let key = key_gen.key(idx, x);
+
+                            let choice: f32 = rng.gen_range(0.0..1.0);
+
+                            if choice < update_ratio {
+                                if workload == Workload::TaskH {
+                                    db.read_modify_write(&key, args.fsync);
+                                } else {
+                                    let val = values.next(&mut rng);
+
+                                    db.insert(&key, val, args.fsync, args.clone());
+                                }
+                            } else {
+                                read(&args, &db, key, || {
+                                    key_gen.key(idx, keys.next(&mut rng, records))
+                                });
+                            }
+
+                            thread_ops[idx as usize].fetch_add(1, Relaxed);
+                        }
+                    })
+                })
+                .collect()
+        }
+
+        Workload::TaskC => (0..ctx.args.threads)
+            .map(|idx| {
+                let ctx = ctx.for_thread(idx as usize);
+
+                // NOTE: All readers share the whole preloaded keyspace
+                spawn(format!("bench-{idx}"), move || {
+                    let WorkerContext {
+                        args,
+                        db,
+                        key_gen,
+                        thread_ops,
+                        stop,
+                        ..
+                    } = ctx;
+
+                    let mut rng = rand::thread_rng();
+
+                    let mut keys = KeyChooser::new(&args, workload);
+
+                    while running(&args, &stop, &thread_ops, idx as usize) {
+                        let x = keys.next(&mut rng, shared_records(&args));
+                        let key = shared_key(&args, &key_gen, x);
+
+                        read(&args, &db, key, || {
+                            shared_key(&args, &key_gen, keys.next(&mut rng, shared_records(&args)))
+                        });
+
+                        thread_ops[idx as usize].fetch_add(1, Relaxed);
+                    }
+                })
+            })
+            .collect(),
+
+        Workload::TaskD | Workload::TaskE | Workload::TaskF | Workload::TaskG => {
+            let insert_ratio = match workload {
+                Workload::TaskD | Workload::TaskF => 0.05,
+                _ => 0.95,
+            };
+
+            (0..ctx.args.threads)
+                .map(|idx| {
+                    let ctx = ctx.for_thread(idx as usize);
+
+                    spawn(format!("bench-{idx}"), move || {
+                        let WorkerContext {
+                            args,
+                            db,
+                            key_gen,
+                            values,
+                            record_counts,
+                            thread_ops,
+                            stop,
+                            ..
+                        } = ctx;
+
+                        let mut rng = rand::thread_rng();
+
+                        let mut keys = KeyChooser::new(&args, workload);
+                        let mut records = record_counts[idx as usize].load(Relaxed);
+
+                        while running(&args, &stop, &thread_ops, idx as usize) {
+                            let choice: f32 = rng.gen_range(0.0..1.0);
+
+                            if choice < insert_ratio {
+                                let val = values.next(&mut rng);
+
+                                let key = key_gen.key(idx, records);
+
+                                db.insert(&key, val, args.fsync, args.clone());
+                                records += 1;
+                            } else {
+                                let x = keys.next(&mut rng, records);
+                                let key = key_gen.key(idx, x);
+
+                                read(&args, &db, key, || {
+                                    key_gen.key(idx, keys.next(&mut rng, records))
+                                });
+                            }
+
+                            thread_ops[idx as usize].fetch_add(1, Relaxed);
+                        }
+
+                        record_counts[idx as usize].store(records, Relaxed);
+                    })
+                })
+                .collect()
+        }
+
+        Workload::TaskI => (0..ctx.args.threads)
+            .map(|idx| {
+                let ctx = ctx.for_thread(idx as usize);
+
+                // NOTE: Transfers go between any records, so threads contend with each other
+                spawn(format!("bench-{idx}"), move || {
+                    let WorkerContext {
+                        args,
+                        db,
+                        key_gen,
+                        thread_ops,
+                        stop,
+                        ..
+                    } = ctx;
+
+                    let mut rng = rand::thread_rng();
+
+                    let mut keys = KeyChooser::new(&args, workload);
+
+                    while running(&args, &stop, &thread_ops, idx as usize) {
+                        transfer(&args, &db, &key_gen, &mut keys, &mut rng);
+
+                        thread_ops[idx as usize].fetch_add(1, Relaxed);
+                    }
+                })
+            })
+            .collect(),
+
+        Workload::TaskM => (0..ctx.args.threads)
+            .map(|idx| {
+                let ctx = ctx.for_thread(idx as usize);
+
+                // NOTE: Lookups may find records of any user, so updates go to the whole keyspace too
+                spawn(format!("bench-{idx}"), move || {
+                    let WorkerContext {
+                        args,
+                        db,
+                        key_gen,
+                        values,
+                        thread_ops,
+                        stop,
+                        ..
+                    } = ctx;
+
+                    let mut rng = rand::thread_rng();
+
+                    let mut keys = KeyChooser::new(&args, workload);
+
+                    while running(&args, &stop, &thread_ops, idx as usize) {
+                        let choice: f32 = rng.gen_range(0.0..1.0);
+
+                        if choice < 0.5 {
+                            let x = keys.next(&mut rng, shared_records(&args));
+                            let key = shared_key(&args, &key_gen, x);
+
+                            index_update(&args, &db, &values, key, &mut rng);
+                        } else {
+                            index_lookup(&args, &db, &mut rng);
+                        }
+
+                        thread_ops[idx as usize].fetch_add(1, Relaxed);
+                    }
+                })
+            })
+            .collect(),
+
+        Workload::TaskJ => start_time_series(ctx),
+
+        Workload::TaskK => start_queue(ctx),
+    }
+}
+
+/// Spawns a named thread, so its CPU time can be told apart from the backend's own threads
+pub fn spawn(name: String, f: impl FnOnce() + Send + 'static) -> JoinHandle<()> {
+    std::thread::Builder::new().name(name).spawn(f).unwrap()
+}
+
+/// Whether a worker thread keeps going, until `stop` is set or it has done its share of `--ops`
+fn running(args: &Args, stop: &AtomicBool, thread_ops: &[Padded<AtomicU64>], idx: usize) -> bool {
+    !stop.load(Relaxed)
+        && args
+            .thread_op_limit(idx)
+            .is_none_or(|limit| thread_ops[idx].load(Relaxed) < limit)
+}
+
+/// Whether all worker threads have done their share of `--ops`
+pub fn ops_done(ctx: &WorkerContext) -> bool {
+    ctx.thread_ops.iter().enumerate().all(|(idx, ops)| {
+        ctx.args
+            .thread_op_limit(idx)
+            .is_some_and(|limit| ops.load(Relaxed) >= limit)
+    })
+}
+
+/// Whether the queue producers, the threads after the `readers` consumers,
+/// have all done their share of `--ops`
+fn producers_done(args: &Args, thread_ops: &[Padded<AtomicU64>], readers: usize) -> bool {
+    (readers..thread_ops.len()).all(|idx| {
+        args.thread_op_limit(idx)
+            .is_some_and(|limit| thread_ops[idx].load(Relaxed) >= limit)
+    })
+}
+
+/// Values written by a workload
+fn workload_values(workload: Workload, ctx: &WorkerContext) -> ValueGenerator {
+    // NOTE: Blobs are generated per workload, as their pool is large
+    if workload == Workload::TaskL {
+        ValueGenerator::blobs(&ctx.args)
+    } else {
+        ctx.values.clone()
+    }
+}
+
+/// Preloaded records, which every thread may access
+pub fn shared_records(args: &Args) -> u64 {
+    u64::from(args.threads) * u64::from(args.items)
+}
+
+/// Key of the N-th preloaded record, counting over all users
+fn shared_key(args: &Args, key_gen: &KeyGenerator, x: u64) -> Vec<u8> {
+    let items = u64::from(args.items);
+    key_gen.key((x / items) as u8, x % items)
+}
+
+/// Spawns `--reader-threads` readers and `--writer-threads` writers,
+/// all picking records from the whole preloaded keyspace
+///
+/// Writers overwrite existing records (or read-modify-write them in workload H,
+/// or transfer between them in workload I), so readers always find their keys
+///
+/// In workload M writers update records with their index entries and readers look up
+/// records by secondary key
+fn start_pools(workload: Workload, ctx: &WorkerContext) -> Vec<JoinHandle<()>> {
+    let readers = usize::from(ctx.args.reader_threads.unwrap_or(0));
+    let values = workload_values(workload, ctx);
+
+    (0..ctx.args.worker_threads())
+        .map(|idx| {
+            let ctx = ctx.for_thread(idx);
+            let values = values.clone();
+            let is_writer = idx >= readers;
+
+            spawn(format!("bench-{idx}"), move || {
+                let WorkerContext {
+                    args,
+                    db,
+                    key_gen,
+                    thread_ops,
+                    stop,
+                    ..
+                } = ctx;
+
+                let mut rng = rand::thread_rng();
+
+                let mut keys = KeyChooser::new(&args, workload);
+
+                while running(&args, &stop, &thread_ops, idx) {
+                    if is_writer && workload == Workload::TaskI {
+                        transfer(&args, &db, &key_gen, &mut keys, &mut rng);
+                    } else if !is_writer && workload == Workload::TaskM {
+                        index_lookup(&args, &db, &mut rng);
+                    } else {
+                        let x = keys.next(&mut rng, shared_records(&args));
+                        let key = shared_key(&args, &key_gen, x);
+
+                        if is_writer && workload == Workload::TaskH {
+                            db.read_modify_write(&key, args.fsync);
+                        } else if is_writer && workload == Workload::TaskM {
+                            index_update(&args, &db, &values, key, &mut rng);
+                        } else if is_writer {
+                            let val = values.next(&mut rng);
+                            db.insert(&key, val, args.fsync, args.clone());
+                        } else {
+                            read(&args, &db, key, || {
+                                shared_key(
+                                    &args,
+                                    &key_gen,
+                                    keys.next(&mut rng, shared_records(&args)),
+                                )
+                            });
+                        }
+                    }
+
+                    thread_ops[idx].fetch_add(1, Relaxed);
+                }
+            })
+        })
+        .collect()
+}
+
+/// Reads `key`, or a batch of `--read-batch-size` keys starting with it
+///
+/// The keys need to exist, unless writes are made to fail using `--redb-fail-after-writes`
+fn read(args: &Args, db: &DatabaseWrapper, key: Vec<u8>, next_key: impl FnMut() -> Vec<u8>) {
+    let batch_size = args.read_batch_size as usize;
+    let may_be_missing = args.redb_fail_after_writes.is_some();
+
+    if batch_size <= 1 {
+        let item = db.get(&key);
+        assert!(item.is_some() || may_be_missing, "key should exist");
+        return;
+    }
+
+    let mut batch = Vec::with_capacity(batch_size);
+    batch.push(key);
+    batch.extend(std::iter::repeat_with(next_key).take(batch_size - 1));
+
+    for item in db.multi_get(&batch) {
+        assert!(item.is_some() || may_be_missing, "key should exist");
+    }
+}
+
+/// Overwrites a record with new secondary keys, moving its index entries in the same batch
+///
+/// The secondary keys are stored in the first 4 bytes per index of the value
+fn index_update<R: Rng>(
+    args: &Args,
+    db: &DatabaseWrapper,
+    values: &ValueGenerator,
+    key: Vec<u8>,
+    rng: &mut R,
+) {
+    let indexes = usize::from(args.secondary_indexes);
+
+    // NOTE: The old entries are found outside the batch, so updates of a record must not interleave
+    let _guard = db.key_locks.lock(&key);
+
+    let old = db.get(&key);
+
+    let mut value = values.next(rng).to_vec();
+    if value.len() < indexes * 4 {
+        value.resize(indexes * 4, 0);
+    }
+
+    let mut puts = Vec::with_capacity(indexes + 1);
+    let mut deletes = Vec::with_capacity(indexes);
+
+    for index in 0..indexes {
+        let attr = rng.gen_range(0..args.index_cardinality);
+        value[index * 4..][..4].copy_from_slice(&attr.to_le_bytes());
+
+        // NOTE: Preloaded values carry random bytes, deleting entries that never existed is harmless
+        let old_attr = old
+            .as_ref()
+            .and_then(|old| old.get(index * 4..index * 4 + 4))
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) % args.index_cardinality);
+
+        if let Some(old_attr) = old_attr.filter(|&old_attr| old_attr != attr) {
+            deletes.push(index_key(index as u8, old_attr, &key));
+        }
+        puts.push((index_key(index as u8, attr, &key), vec![]));
+    }
+
+    puts.push((key, value));
+
+    db.write_batch(&puts, &deletes, args.fsync);
+}
+
+/// Looks up the records of a random secondary key, using a prefix scan over its index entries
+fn index_lookup<R: Rng>(args: &Args, db: &DatabaseWrapper, rng: &mut R) {
+    let index = rng.gen_range(0..args.secondary_indexes);
+    let attr = rng.gen_range(0..args.index_cardinality);
+
+    let primary_keys = db
+        .scan(&index_prefix(index, attr), &index_prefix(index, attr + 1))
+        .into_iter()
+        .map(|key| key[8..].to_vec())
+        .collect::<Vec<_>>();
+
+    // NOTE: Records may have been updated since the scan, so they don't need to match anymore
+    if !primary_keys.is_empty() {
+        db.multi_get(&primary_keys);
+    }
+}
+
+/// Moves a random amount between two records of the shared keyspace
+fn transfer<R: Rng>(
+    args: &Args,
+    db: &DatabaseWrapper,
+    key_gen: &KeyGenerator,
+    keys: &mut KeyChooser,
+    rng: &mut R,
+) {
+    let from = shared_key(args, key_gen, keys.next(rng, shared_records(args)));
+    let to = shared_key(args, key_gen, keys.next(rng, shared_records(args)));
+
+    db.transfer(&from, &to, rng.gen_range(1..=100), args.fsync);
+}
+
+/// Sum of the counters of all shared records, which transfers must not change
+pub fn total_balance(ctx: &WorkerContext) -> u64 {
+    (0..shared_records(&ctx.args))
+        .map(|x| {
+            let key = shared_key(&ctx.args, &ctx.key_gen, x);
+            let value = ctx.db.get(&key).unwrap_or_default();
+            counter(&value)
+        })
+        .fold(0, u64::wrapping_add)
+}
+
+/// Spawns one appender per series, plus the retention thread
+///
+/// With thread pools, writers append and readers scan random series,
+/// otherwise every thread appends to and scans its own series
+fn start_time_series(ctx: &WorkerContext) -> Vec<JoinHandle<()>> {
+    assert!(
+        ctx.args.key_order == KeyOrder::Ordered,
+        "The time series workload needs --key-order ordered, as range deletes could hit hashed keys"
+    );
+
+    let readers = usize::from(ctx.args.reader_threads.unwrap_or(0));
+    let series_count = (ctx.args.worker_threads() - readers) as u8;
+
+    let mut threads = (0..ctx.args.worker_threads())
+        .map(|idx| {
+            let ctx = ctx.for_thread(idx);
+
+            spawn(format!("bench-{idx}"), move || {
+                let WorkerContext {
+                    args,
+                    db,
+                    key_gen,
+                    values,
+                    thread_ops,
+                    stop,
+                    epoch,
+                    ..
+                } = ctx;
+
+                let mut rng = rand::thread_rng();
+
+                let window = u64::from(args.scan_window_ms) * 1_000;
+                let mut last_ts = 0;
+
+                while running(&args, &stop, &thread_ops, idx) {
+                    let now = epoch.elapsed().as_micros() as u64;
+
+                    let is_scan = if args.uses_thread_pools() {
+                        idx < readers
+                    } else {
+                        rng.gen_bool(0.05)
+                    };
+
+                    if is_scan {
+                        let series = if args.uses_thread_pools() {
+                            rng.gen_range(0..series_count.max(1))
+                        } else {
+                            idx as u8
+                        };
+
+                        db.scan(
+                            &series_prefix(series, now.saturating_sub(window)),
+                            &series_prefix(series, now + 1),
+                        );
+                    } else {
+                        // NOTE: Timestamps must be unique per series
+                        let ts = now.max(last_ts + 1);
+                        last_ts = ts;
+
+                        let key = key_gen.series_key((idx - readers) as u8, ts);
+                        let val = values.next(&mut rng);
+
+                        db.insert(&key, val, args.fsync, args.clone());
+                    }
+
+                    thread_ops[idx].fetch_add(1, Relaxed);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let ctx = ctx.clone();
+
+    threads.push(spawn("bench-retention".into(), move || {
+        let retention = u64::from(ctx.args.retention_secs) * 1_000_000;
+
+        let mut deleted_until = 0;
+        let mut next_run = Instant::now() + RETENTION_INTERVAL;
+
+        while !ctx.stop.load(Relaxed) {
+            if Instant::now() < next_run {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            next_run += RETENTION_INTERVAL;
+
+            let cutoff = (ctx.epoch.elapsed().as_micros() as u64).saturating_sub(retention);
+
+            if cutoff <= deleted_until {
+                continue;
+            }
+
+            for series in 0..series_count {
+                ctx.db.delete_range(
+                    &series_prefix(series, deleted_until),
+                    &series_prefix(series, cutoff),
+                    ctx.args.fsync,
+                );
+            }
+
+            deleted_until = cutoff;
+        }
+    }));
+
+    threads
+}
+
+/// Spawns queue producers and consumers
+///
+/// With thread pools, writers produce and readers consume,
+/// otherwise every thread does both
+fn start_queue(ctx: &WorkerContext) -> Vec<JoinHandle<()>> {
+    assert!(
+        ctx.args.key_order == KeyOrder::Ordered,
+        "The queue workload needs --key-order ordered, as pops could hit hashed keys"
+    );
+
+    let readers = usize::from(ctx.args.reader_threads.unwrap_or(0));
+
+    (0..ctx.args.worker_threads())
+        .map(|idx| {
+            let ctx = ctx.for_thread(idx);
+
+            spawn(format!("bench-{idx}"), move || {
+                let WorkerContext {
+                    args,
+                    db,
+                    key_gen,
+                    values,
+                    thread_ops,
+                    stop,
+                    queue_tail,
+                    ..
+                } = ctx;
+
+                let mut rng = rand::thread_rng();
+
+                let head = queue_prefix(0);
+                let end = queue_prefix(u64::MAX);
+
+                while running(&args, &stop, &thread_ops, idx) {
+                    let is_pop = if args.uses_thread_pools() {
+                        idx < readers
+                    } else {
+                        rng.gen_bool(0.5)
+                    };
+
+                    if is_pop {
+                        // NOTE: Checked before popping, so an item pushed after an empty pop
+                        // cannot be left behind
+                        let drained =
+                            args.uses_thread_pools() && producers_done(&args, &thread_ops, readers);
+
+                        // NOTE: Always look from the head, stepping over whatever
+                        // the previous pops left behind (e.g. tombstones)
+                        if db.pop_first(&head, &end, args.fsync).is_none() {
+                            // NOTE: Nothing is going to be pushed anymore, so the share
+                            // of --ops may never be reached
+                            if drained {
+                                break;
+                            }
+
+                            // NOTE: Not an operation, so it does not count towards --ops either
+                            std::thread::yield_now();
+                            continue;
+                        }
+                    } else {
+                        let key = key_gen.queue_key(queue_tail.fetch_add(1, Relaxed));
+                        let val = values.next(&mut rng);
+
+                        db.insert(&key, val, args.fsync, args.clone());
+                    }
+
+                    thread_ops[idx].fetch_add(1, Relaxed);
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counters::Counters;
+    use crate::db::GenericDatabase;
+    use clap::Parser;
+    use std::sync::mpsc;
+
+    fn sled_context(args: &[&str]) -> WorkerContext {
+        let args = Args::parse_from(
+            [
+                "worker",
+                "--backend",
+                "sled",
+                "--items",
+                "0",
+                "--key-size",
+                "8",
+                "--value-size",
+                "16",
+                "--out",
+                "unused.jsonl",
+            ]
+            .iter()
+            .chain(args),
+        );
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let db = DatabaseWrapper {
+            inner: GenericDatabase::Sled {
+                trees: vec![(*db).clone()],
+                db,
+            },
+            counters: Arc::new(
+                (0..=args.worker_threads())
+                    .map(|_| Counters::default())
+                    .collect(),
+            ),
+            slot: args.worker_threads(),
+            key_locks: Default::default(),
+        };
+
+        WorkerContext::new(Arc::new(args), db)
+    }
+
+    #[test]
+    fn queue_consumers_stop_once_producers_are_done() {
+        let ctx = sled_context(&[
+            "--workload",
+            "task-k",
+            "--reader-threads",
+            "2",
+            "--writer-threads",
+            "1",
+            "--ops",
+            "300",
+        ]);
+
+        let threads = start_queue(&ctx);
+
+        // NOTE: The consumers' share of --ops can never be reached, as only 100 items are pushed
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for thread in threads {
+                thread.join().unwrap();
+            }
+            tx.send(()).unwrap();
+        });
+
+        rx.recv_timeout(Duration::from_secs(30))
+            .expect("queue threads did not finish");
+
+        assert_eq!(ctx.thread_ops[2].load(Relaxed), 100);
+        assert!(ctx
+            .db
+            .pop_first(&queue_prefix(0), &queue_prefix(u64::MAX), false)
+            .is_none());
+    }
+}